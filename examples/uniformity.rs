@@ -0,0 +1,62 @@
+use sudoku::{seed_rng, Grid, CELLS};
+
+const SAMPLES: usize = 2000;
+const POOL: usize = 32;
+
+// Critical value of the chi-squared distribution with 5 degrees of freedom at p = 0.01.
+const CRITICAL: f64 = 15.086;
+
+fn digit(grid: &Grid, row: usize, column: usize) -> u8 {
+    grid[CELLS[row * 9 + column]].state().digit().map_or(0, u8::from)
+}
+
+fn minirow(grid: &Grid, row: usize, column: usize) -> u16 {
+    (0..3).fold(0, |set, x| set | 1 << digit(grid, row, column + x))
+}
+
+fn minicolumn(grid: &Grid, row: usize, column: usize) -> u16 {
+    (0..3).fold(0, |set, x| set | 1 << digit(grid, row + x, column))
+}
+
+// A band is pure when the second minirow of its first row holds the same digits as one of the
+// first minirows of the two other rows. Stacks are handled the same way with minicolumns.
+fn features(grid: &Grid) -> [bool; 6] {
+    let mut features = [false; 6];
+    for index in 0..3 {
+        let first = index * 3;
+        let set = minirow(grid, first, 3);
+        features[index] = set == minirow(grid, first + 1, 0) || set == minirow(grid, first + 2, 0);
+        let set = minicolumn(grid, 3, first);
+        features[index + 3] = set == minicolumn(grid, 0, first + 1) || set == minicolumn(grid, 0, first + 2);
+    }
+    features
+}
+
+fn main() {
+    let mut rng = seed_rng(0);
+
+    let mut counts = [0usize; 6];
+    for _ in 0..SAMPLES {
+        let grid = Grid::sample(&mut rng, POOL);
+        for (count, feature) in counts.iter_mut().zip(features(&grid).iter()) {
+            if *feature {
+                *count += 1;
+            }
+        }
+    }
+
+    // Bands and stacks are interchangeable under transposition and band/stack permutations, so a
+    // uniform sampler must find pure bands and pure stacks at the same rate.
+    let total = counts.iter().sum::<usize>() as f64;
+    let expected = total / counts.len() as f64;
+    let statistic: f64 = counts.iter().map(|&x| (x as f64 - expected).powi(2) / expected).sum();
+
+    println!("pure bands {:?}, pure stacks {:?}", &counts[..3], &counts[3..]);
+    println!("chi-squared = {:.3} (critical {:.3})", statistic, CRITICAL);
+
+    if statistic > CRITICAL {
+        println!("FAIL: pure band and stack frequencies differ");
+        std::process::exit(1);
+    }
+    println!("OK");
+}
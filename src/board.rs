@@ -0,0 +1,348 @@
+use std::{fmt, str::FromStr};
+use rand::Rng;
+use crate::{search::{self, Constraints, Search}, Grid, GridState, CELLS};
+
+const SYMBOLS: &[u8; 25] = b"123456789ABCDEFGHIJKLMNOP";
+
+// Boxes are W columns wide and H rows high, so the side is W * H. Each supported shape has storage
+// sized to its cell count and group tables built at compile time. Boards only solve and generate:
+// strategies, transforms and variants work on the 9x9 `Grid`.
+pub trait Geometry {
+    type Options: AsRef<[u32]> + AsMut<[u32]> + Clone + fmt::Debug;
+    const EMPTY: Self::Options;
+    // The cells of every row, then every column, then every box.
+    const MEMBERS: &'static [u16];
+    // The row, column and box of every cell.
+    const GROUPS: &'static [u16];
+}
+
+#[derive(Debug)]
+pub struct Shape<const W: usize, const H: usize>;
+
+const fn members<const N: usize>(w: usize, h: usize) -> [u16; N] {
+    let side = w * h;
+    let mut members = [0; N];
+    let mut index = 0;
+    while index < N {
+        let (group, member) = (index / side, index % side);
+        let which = group % side;
+        let cell = match group / side {
+            0 => which * side + member,
+            1 => member * side + which,
+            _ => ((which / h) * h + member / w) * side + (which % h) * w + member % w,
+        };
+        members[index] = cell as u16;
+        index += 1;
+    }
+    members
+}
+
+const fn groups<const N: usize>(w: usize, h: usize) -> [u16; N] {
+    let side = w * h;
+    let mut groups = [0; N];
+    let mut cell = 0;
+    while cell * 3 < N {
+        let (row, column) = (cell / side, cell % side);
+        groups[cell * 3] = row as u16;
+        groups[cell * 3 + 1] = (side + column) as u16;
+        groups[cell * 3 + 2] = (side * 2 + (row / h) * h + column / w) as u16;
+        cell += 1;
+    }
+    groups
+}
+
+macro_rules! geometry {
+    ($($w:literal x $h:literal),*) => {$(
+        impl Geometry for Shape<$w, $h> {
+            type Options = [u32; $w * $h * $w * $h];
+            const EMPTY: Self::Options = [0; $w * $h * $w * $h];
+            const MEMBERS: &'static [u16] = &members::<{ 3 * $w * $h * $w * $h }>($w, $h);
+            const GROUPS: &'static [u16] = &groups::<{ 3 * $w * $h * $w * $h }>($w, $h);
+        }
+    )*};
+}
+
+geometry!(2 x 2, 3 x 2, 3 x 3, 4 x 3, 4 x 4, 5 x 5);
+
+#[derive(Debug, Clone)]
+pub struct Board<const W: usize, const H: usize> where Shape<W, H>: Geometry {
+    options: <Shape<W, H> as Geometry>::Options,
+}
+
+pub type Board4 = Board<2, 2>;
+pub type Board6 = Board<3, 2>;
+pub type Board9 = Board<3, 3>;
+pub type Board12 = Board<4, 3>;
+pub type Board16 = Board<4, 4>;
+pub type Board25 = Board<5, 5>;
+
+impl<const W: usize, const H: usize> Board<W, H> where Shape<W, H>: Geometry {
+    pub const SIDE: usize = W * H;
+    const CELLS: usize = Self::SIDE * Self::SIDE;
+    const GROUPS: usize = Self::SIDE * 3;
+    const ALL: u32 = ((1u64 << (W * H)) - 1) as u32;
+
+    pub fn new() -> Self {
+        let mut options = <Shape<W, H> as Geometry>::EMPTY;
+        for value in options.as_mut() {
+            *value = Self::ALL;
+        }
+        Board { options }
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> Option<u8> {
+        let options = self.options.as_ref()[Self::cell(row, column)?];
+        if options.is_power_of_two() {
+            Some(options.trailing_zeros() as u8 + 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn options(&self, row: usize, column: usize) -> u32 {
+        Self::cell(row, column).map_or(0, |x| self.options.as_ref()[x])
+    }
+
+    pub fn set(&mut self, row: usize, column: usize, digit: u8) -> bool {
+        match Self::cell(row, column) {
+            Some(cell) if digit >= 1 && digit as usize <= Self::SIDE => {
+                self.options.as_mut()[cell] = 1 << (digit - 1);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn reset(&mut self, row: usize, column: usize) {
+        if let Some(cell) = Self::cell(row, column) {
+            self.options.as_mut()[cell] = Self::ALL;
+        }
+    }
+
+    pub fn state(&self) -> GridState {
+        self.options.as_ref().iter().fold(GridState::Complete, |state, &options| match state {
+            GridState::Impossible => GridState::Impossible,
+            _ if options == 0 => GridState::Impossible,
+            GridState::Incomplete => GridState::Incomplete,
+            GridState::Complete if options.is_power_of_two() => GridState::Complete,
+            GridState::Complete => GridState::Incomplete,
+        })
+    }
+
+    pub fn solve(&mut self) -> GridState {
+        search::propagate(&Shape::<W, H>, self.options.as_mut())
+    }
+
+    pub fn count_solutions(&self, limit: usize) -> usize {
+        search::count_solutions(self, limit)
+    }
+
+    pub fn solutions(&self, limit: usize) -> Vec<Self> {
+        search::solutions(self, limit)
+    }
+
+    pub fn is_unique(&self) -> bool {
+        search::is_unique(self)
+    }
+
+    pub fn bruteforce<R: Rng>(&mut self, rng: &mut R) -> bool {
+        search::bruteforce(self, rng)
+    }
+
+    pub fn solution<R: Rng>(rng: &mut R) -> Self {
+        let mut board = Self::new();
+        board.bruteforce(rng);
+        board
+    }
+
+    pub fn generate<R: Rng>(rng: &mut R) -> Self {
+        search::dig(&Self::solution(rng), rng, |_| true)
+    }
+
+    fn cell(row: usize, column: usize) -> Option<usize> {
+        if row < Self::SIDE && column < Self::SIDE {
+            Some(row * Self::SIDE + column)
+        } else {
+            None
+        }
+    }
+
+    fn groups(cell: usize) -> &'static [u16] {
+        &<Shape<W, H> as Geometry>::GROUPS[cell * 3..cell * 3 + 3]
+    }
+
+    fn members(group: usize) -> &'static [u16] {
+        &<Shape<W, H> as Geometry>::MEMBERS[group * Self::SIDE..(group + 1) * Self::SIDE]
+    }
+}
+
+impl<const W: usize, const H: usize> Constraints for Shape<W, H> where Shape<W, H>: Geometry {
+    type Member = u16;
+
+    fn digits(&self) -> u32 {
+        Board::<W, H>::ALL
+    }
+
+    fn houses(&self) -> usize {
+        Board::<W, H>::GROUPS
+    }
+
+    fn house(&self, index: usize) -> &[u16] {
+        Board::<W, H>::members(index)
+    }
+
+    fn peers<F: FnMut(usize)>(&self, cell: usize, mut visit: F) {
+        for &group in Board::<W, H>::groups(cell) {
+            for &peer in Board::<W, H>::members(group as usize) {
+                if peer as usize != cell {
+                    visit(peer as usize);
+                }
+            }
+        }
+    }
+}
+
+impl<const W: usize, const H: usize> Search for Board<W, H> where Shape<W, H>: Geometry {
+    fn size(&self) -> usize {
+        Self::CELLS
+    }
+
+    fn propagate(&mut self) -> GridState {
+        self.solve()
+    }
+
+    fn candidates(&self, cell: usize) -> u32 {
+        self.options.as_ref()[cell]
+    }
+
+    fn assign(&mut self, cell: usize, digit: u32) {
+        self.options.as_mut()[cell] = 1 << digit;
+    }
+
+    fn clear(&mut self, cell: usize) {
+        self.options.as_mut()[cell] = Self::ALL;
+    }
+}
+
+impl<const W: usize, const H: usize> Default for Board<W, H> where Shape<W, H>: Geometry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const W: usize, const H: usize> FromStr for Board<W, H> where Shape<W, H>: Geometry {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let symbols = &SYMBOLS[..Self::SIDE];
+        let mut board = Self::new();
+        let mut cell = 0;
+
+        for byte in s.bytes() {
+            let digit = match byte {
+                b'.' | b'0' => None,
+                _ => match symbols.iter().position(|&x| x == byte.to_ascii_uppercase()) {
+                    Some(position) => Some(position),
+                    None if byte.is_ascii_alphanumeric() => return Err("Invalid symbol"),
+                    None => continue,
+                },
+            };
+            if cell == Self::CELLS {
+                return Err("Too many cells");
+            }
+            if let Some(digit) = digit {
+                board.options.as_mut()[cell] = 1 << digit;
+            }
+            cell += 1;
+        }
+
+        if cell < Self::CELLS {
+            return Err("Too few cells");
+        }
+        Ok(board)
+    }
+}
+
+impl<const W: usize, const H: usize> fmt::Display for Board<W, H> where Shape<W, H>: Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let border = format!("+{}", "-".repeat(W * 4 - 1)).repeat(H) + "+";
+        let spacer = format!("|{}", " ".repeat(W * 4 - 1)).repeat(H) + "|";
+
+        for row in 0..Self::SIDE {
+            writeln!(f, "{}", if row % H == 0 { &border } else { &spacer })?;
+            for column in 0..Self::SIDE {
+                let options = self.options.as_ref()[row * Self::SIDE + column];
+                let symbol = match options {
+                    0 => 'X',
+                    _ if options.is_power_of_two() => SYMBOLS[options.trailing_zeros() as usize] as char,
+                    _ => '.',
+                };
+                write!(f, "{} {} ", if column % W == 0 { '|' } else { ' ' }, symbol)?;
+            }
+            writeln!(f, "|")?;
+        }
+        write!(f, "{}", border)
+    }
+}
+
+impl From<&Grid> for Board9 {
+    fn from(grid: &Grid) -> Self {
+        let mut board = Board9::new();
+        for &cell in &CELLS {
+            board.options.as_mut()[cell.index() as usize] = u32::from(grid[cell].options);
+        }
+        board
+    }
+}
+
+impl From<&Board9> for Grid {
+    fn from(board: &Board9) -> Self {
+        let mut grid = Grid::default();
+        for &cell in &CELLS {
+            grid[cell].options = board.options.as_ref()[cell.index() as usize] as u16;
+        }
+        grid
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{seed_rng, Board, Board12, Board16, Board25, Board4, Board6, Board9, GridState};
+    use super::{Geometry, Shape};
+
+    fn is_valid<const W: usize, const H: usize>(board: &Board<W, H>) -> bool where Shape<W, H>: Geometry {
+        let side = W * H;
+        let (rows, columns) = (0..side).map(|x| (x, 0..side)).fold((true, true), |(rows, columns), (x, range)| {
+            let row = range.clone().fold(0, |set, y| set | board.options(x, y));
+            let column = range.fold(0, |set, y| set | board.options(y, x));
+            (rows && row.count_ones() as usize == side, columns && column.count_ones() as usize == side)
+        });
+        let boxes = (0..side).all(|index| {
+            let (top, left) = (index / H * H, index % H * W);
+            let set = (0..side).fold(0, |set, x| set | board.options(top + x / W, left + x % W));
+            set.count_ones() as usize == side
+        });
+        matches!(board.state(), GridState::Complete) && rows && columns && boxes
+    }
+
+    #[test]
+    fn every_shape_solves_and_generates() {
+        let mut rng = seed_rng(0);
+        assert!(is_valid(&Board25::solution(&mut rng)));
+        assert!(is_valid(&Board16::solution(&mut rng)));
+        assert!(Board12::generate(&mut rng).is_unique());
+        assert!(Board9::generate(&mut rng).is_unique());
+        assert!(Board6::generate(&mut rng).is_unique());
+        assert!(Board4::generate(&mut rng).is_unique());
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let text = "12.4\n3..2\n2.4.\n.3.1";
+        let board: Board4 = text.parse().unwrap();
+        assert_eq!(board.to_string().parse::<Board4>().unwrap().to_string(), board.to_string());
+        assert_eq!(board.get(0, 3), Some(4));
+        assert_eq!(board.solutions(2).len(), 1);
+    }
+}
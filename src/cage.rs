@@ -0,0 +1,256 @@
+use std::{fmt, str::FromStr};
+use crate::{Cell, Grid};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cage {
+    pub cells: Vec<Cell>,
+    pub sum: u8,
+}
+
+impl Cage {
+    pub fn new(cells: Vec<Cell>, sum: u8) -> Result<Cage, &'static str> {
+        let len = cells.len() as u8;
+        if len == 0 || len > 9 {
+            return Err("Cage must have between one and nine cells");
+        }
+        if cells.iter().enumerate().any(|(index, cell)| cells[..index].contains(cell)) {
+            return Err("Cage cannot contain a cell twice");
+        }
+        if sum < len * (len + 1) / 2 || sum > len * (19 - len) / 2 {
+            return Err("Cage sum is out of range");
+        }
+        Ok(Cage { cells, sum })
+    }
+}
+
+impl FromStr for Cage {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split_whitespace();
+        let sum = tokens.next().ok_or("Missing cage sum")?.parse().map_err(|_| "Invalid cage sum")?;
+        let cells = tokens.map(parse_cell).collect::<Result<_, _>>()?;
+        Cage::new(cells, sum)
+    }
+}
+
+fn parse_cell(token: &str) -> Result<Cell, &'static str> {
+    match token.as_bytes() {
+        &[b'r', row @ b'1'..=b'9', b'c', column @ b'1'..=b'9'] => Ok(Cell::in_row(row - b'1', column - b'1')),
+        _ => Err("Invalid cell"),
+    }
+}
+
+impl fmt::Display for Cage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.sum)?;
+        for cell in &self.cells {
+            write!(f, " r{}c{}", cell.row(), cell.column())?;
+        }
+        Ok(())
+    }
+}
+
+// A sum constraint over up to nine cells, either a cage or one derived from the rule of 45.
+#[derive(Debug, Clone)]
+pub(crate) struct Sum {
+    pub(crate) cells: Vec<Cell>,
+    pub(crate) total: u8,
+    pub(crate) distinct: bool,
+}
+
+impl Sum {
+    // Keeps the candidates that take part in at least one completion of the sum. Returns None
+    // when there is no completion at all.
+    pub(crate) fn restrict(&self, grid: &mut Grid) -> Option<bool> {
+        let len = self.cells.len();
+        let reached = self.reach(grid);
+
+        let mut completes = vec![vec![false; self.states()]; len + 1];
+        for (state, completes) in completes[len].iter_mut().enumerate() {
+            *completes = reached[len][state] && self.total(state) == self.total as usize;
+        }
+        let mut allowed = vec![0u16; len];
+        for (index, &cell) in self.cells.iter().enumerate().rev() {
+            for state in 0..self.states() {
+                if !reached[index][state] {
+                    continue;
+                }
+                for digit in 0..9 {
+                    match self.step(state, digit) {
+                        Some(next) if grid[cell].options & 1 << digit != 0 && completes[index + 1][next] => {
+                            completes[index][state] = true;
+                            allowed[index] |= 1 << digit;
+                        },
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        let mut changed = false;
+        for (&cell, &allowed) in self.cells.iter().zip(&allowed) {
+            if allowed == 0 {
+                return None;
+            }
+            if grid[cell].options & !allowed != 0 {
+                grid[cell].options &= allowed;
+                changed = true;
+            }
+        }
+        Some(changed)
+    }
+
+    // The digits that every completion of a sum of distinct digits uses.
+    pub(crate) fn required(&self, grid: &Grid) -> u16 {
+        if !self.distinct {
+            return 0;
+        }
+        let reached = self.reach(grid);
+        (0..self.states())
+            .filter(|&x| reached[self.cells.len()][x] && self.total(x) == self.total as usize)
+            .fold((1 << 9) - 1, |required, x| required & x as u16)
+    }
+
+    // Distinct sums track the set of digits used so far, the others only the running total.
+    fn states(&self) -> usize {
+        if self.distinct { 1 << 9 } else { 82 }
+    }
+
+    fn step(&self, state: usize, digit: usize) -> Option<usize> {
+        let next = if self.distinct {
+            if state & 1 << digit != 0 {
+                return None;
+            }
+            state | 1 << digit
+        } else {
+            state + digit + 1
+        };
+        if next < self.states() { Some(next) } else { None }
+    }
+
+    fn total(&self, state: usize) -> usize {
+        if self.distinct {
+            (0..9).filter(|x| state & 1 << x != 0).map(|x| x + 1).sum()
+        } else {
+            state
+        }
+    }
+
+    fn reach(&self, grid: &Grid) -> Vec<Vec<bool>> {
+        let mut reached = vec![vec![false; self.states()]; self.cells.len() + 1];
+        reached[0][0] = true;
+        for (index, &cell) in self.cells.iter().enumerate() {
+            for state in 0..self.states() {
+                if !reached[index][state] {
+                    continue;
+                }
+                for digit in 0..9 {
+                    match self.step(state, digit) {
+                        Some(next) if grid[cell].options & 1 << digit != 0 => reached[index + 1][next] = true,
+                        _ => (),
+                    }
+                }
+            }
+        }
+        reached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{seed_rng, Cage, Cell, Digit, Grid, Hint, Technique, Variant, TECHNIQUES};
+    use super::Sum;
+
+    fn row(row: u8, columns: std::ops::Range<u8>) -> Vec<Cell> {
+        columns.map(|x| Cell::in_row(row, x)).collect()
+    }
+
+    #[test]
+    fn cage_text_round_trips() {
+        let cage: Cage = "15 r1c1 r1c2 r2c1".parse().unwrap();
+        assert_eq!(cage.cells, vec![Cell::in_row(0, 0), Cell::in_row(0, 1), Cell::in_row(1, 0)]);
+        assert_eq!(cage.to_string(), "15 r1c1 r1c2 r2c1");
+        assert!("3 r1c1 r1c1".parse::<Cage>().is_err());
+        assert!("2 r1c1 r1c2".parse::<Cage>().is_err());
+        assert!("18 r1c1 r1c2".parse::<Cage>().is_err());
+        assert!("5 r0c1".parse::<Cage>().is_err());
+    }
+
+    #[test]
+    fn sums_keep_only_digits_of_some_combination() {
+        let mut grid = Grid::default();
+        let sum = Sum { cells: row(0, 0..2), total: 17, distinct: true };
+        assert_eq!(sum.restrict(&mut grid), Some(true));
+        assert_eq!(grid[Cell::in_row(0, 0)].options, 0b1_1000_0000);
+
+        grid[Cell::in_row(0, 0)].set(Digit::Eight);
+        assert_eq!(sum.restrict(&mut grid), Some(true));
+        assert!(grid[Cell::in_row(0, 1)].is(Digit::Nine));
+        assert_eq!(sum.restrict(&mut grid), Some(false));
+
+        // Without distinct digits, 18 over two cells can only be two nines.
+        let mut grid = Grid::default();
+        let sum = Sum { cells: row(0, 0..2), total: 18, distinct: false };
+        assert_eq!(sum.restrict(&mut grid), Some(true));
+        assert!(grid[Cell::in_row(0, 0)].is(Digit::Nine) && grid[Cell::in_row(0, 1)].is(Digit::Nine));
+
+        grid[Cell::in_row(0, 0)].set(Digit::One);
+        assert_eq!(sum.restrict(&mut grid), None);
+    }
+
+    #[test]
+    fn rule_of_45_finds_innies_and_outies() {
+        // Cages cover r1c1-r1c8 with 40, leaving 5 for the innie r1c9.
+        let cages = vec![Cage::new(row(0, 0..4), 20).unwrap(), Cage::new(row(0, 4..8), 20).unwrap()];
+        let variant = Variant::classic().with_cages(cages).unwrap();
+        let mut grid = Grid::default();
+        variant.solve(&mut grid);
+        assert!(grid[Cell::in_row(0, 8)].is(Digit::Five));
+
+        // Cages cover box 1 and stick out into r4c1 by 50 - 45.
+        let cells = |cells: &[(u8, u8)]| cells.iter().map(|&(x, y)| Cell::in_row(x, y)).collect();
+        let cages = vec![
+            Cage::new(cells(&[(0, 0), (0, 1), (0, 2), (1, 0), (1, 1)]), 25).unwrap(),
+            Cage::new(cells(&[(1, 2), (2, 0), (2, 1), (2, 2), (3, 0)]), 25).unwrap(),
+        ];
+        let variant = Variant::classic().with_cages(cages).unwrap();
+        let mut grid = Grid::default();
+        variant.solve(&mut grid);
+        assert!(grid[Cell::in_row(3, 0)].is(Digit::Five));
+    }
+
+    #[test]
+    fn techniques_use_cage_sums() {
+        // Without givens, only the cage sums can get the techniques started.
+        let solution = Variant::classic().solution(&mut seed_rng(0)).unwrap();
+        let mut cages = Vec::new();
+        for index in 0..9 {
+            let others: Vec<u8> = (0..9).filter(|&x| x != index).collect();
+            for columns in std::iter::once(&[index][..]).chain(others.chunks(2)) {
+                let cells: Vec<Cell> = columns.iter().map(|&x| Cell::in_row(index, x)).collect();
+                let sum = cells.iter().map(|&x| u8::from(solution[x].state().digit().unwrap())).sum();
+                cages.push(Cage::new(cells, sum).unwrap());
+            }
+        }
+        let variant = Variant::classic().with_cages(cages).unwrap();
+        let logical = variant.solve_logically(&Grid::default(), &TECHNIQUES);
+        assert!(logical.steps.iter().any(|x| x.technique == Technique::CageCombination));
+        for step in &logical.steps {
+            assert!(step.placement.iter().all(|&(cell, digit)| solution[cell].is(digit)));
+            assert!(step.eliminations.iter().all(|&(cell, digit)| !solution[cell].is(digit)));
+        }
+        assert!((0..9).all(|x| logical.state[Cell::in_row(x, x)].state().is_defined()));
+
+        // The innie r1c9 is worth 5, which no single cage tells.
+        let cages = vec![Cage::new(row(0, 0..4), 20).unwrap(), Cage::new(row(0, 4..8), 20).unwrap()];
+        let variant = Variant::classic().with_cages(cages).unwrap();
+        let step = match variant.hint(&Grid::default(), None) {
+            Some(Hint::Deduction(step)) => step,
+            hint => panic!("unexpected hint {:?}", hint),
+        };
+        assert_eq!(step.technique, Technique::RuleOf45);
+        assert_eq!(step.to_string().split(" =>").next(), Some("Rule of 45: r1c9 can only hold 5"));
+        assert_eq!(step.eliminations.iter().filter(|&&(x, _)| x == Cell::in_row(0, 8)).count(), 8);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{Cell, Grid, Variant, CELLS};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    // Entries that differ from the solution, or None when the puzzle has no single solution to
+    // compare against.
+    pub mistakes: Option<Vec<Cell>>,
+    pub conflicts: Vec<(Cell, Cell)>,
+    pub solvable: bool,
+}
+
+impl Check {
+    pub fn is_clean(&self) -> bool {
+        self.mistakes.as_ref().map_or(true, Vec::is_empty) && self.conflicts.is_empty() && self.solvable
+    }
+}
+
+impl Grid {
+    pub fn check(&self, board: &Grid) -> Check {
+        Variant::classic().check(self, board)
+    }
+
+    pub(crate) fn conflict(&self) -> Option<(Cell, Cell)> {
+        CELLS.iter().copied().filter(|&x| self[x].state().is_defined()).find_map(|cell| {
+            cell.neighbors().iter().copied()
+                .find(|&x| x.index() > cell.index() && self[x].options == self[cell].options)
+                .map(|x| (cell, x))
+        })
+    }
+}
+
+impl Variant {
+    pub fn check(&self, puzzle: &Grid, board: &Grid) -> Check {
+        let entries: Vec<Cell> = CELLS.iter().copied()
+            .filter(|&x| board[x].state().is_defined() && !puzzle[x].state().is_defined())
+            .collect();
+
+        let mut solutions = self.solutions(puzzle, 2);
+        let mistakes = match solutions.pop() {
+            Some(solution) if solutions.is_empty() => {
+                Some(entries.iter().copied().filter(|&x| board[x].options != solution[x].options).collect())
+            },
+            _ => None,
+        };
+
+        let mut conflicts = Vec::new();
+        for &cell in &entries {
+            for neighbor in self.neighbors(cell) {
+                if board[neighbor].options != board[cell].options {
+                    continue;
+                }
+                let pair = if cell.index() < neighbor.index() { (cell, neighbor) } else { (neighbor, cell) };
+                if !conflicts.contains(&pair) {
+                    conflicts.push(pair);
+                }
+            }
+        }
+        conflicts.sort_by_key(|&(x, y)| (x.index(), y.index()));
+
+        let mut current = Grid::default();
+        for &cell in &CELLS {
+            if board[cell].state().is_defined() {
+                current[cell] = board[cell].clone();
+            }
+        }
+
+        Check {
+            mistakes,
+            conflicts,
+            solvable: self.count_solutions(&current, 1) > 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Board9, Cell, Grid, CELLS, DIGITS};
+
+    const PUZZLE: &str = "42....6...6.1....7..5..4....1...7.8...2..1.96..892.......3.2..1.8.....3..4.7.....";
+
+    #[test]
+    fn check_compares_entries_with_the_solution() {
+        let puzzle = Grid::from(&PUZZLE.parse::<Board9>().unwrap());
+        let solution = puzzle.solutions(1).pop().unwrap();
+        let empty: Vec<Cell> = CELLS.iter().copied().filter(|&x| !puzzle[x].state().is_defined()).collect();
+
+        let mut board = puzzle.clone();
+        board[empty[0]] = solution[empty[0]].clone();
+        assert!(puzzle.check(&board).is_clean());
+
+        // A wrong digit that no given rules out is a mistake but not a conflict.
+        let (cell, digit) = empty[1..].iter()
+            .flat_map(|&x| DIGITS.iter().map(move |&y| (x, y)))
+            .find(|&(x, y)| !solution[x].is(y) && x.neighbors().iter().all(|&z| !puzzle[z].is(y)))
+            .unwrap();
+        board[cell].set(digit);
+        let check = puzzle.check(&board);
+        assert_eq!(check.mistakes, Some(vec![cell]));
+        assert!(check.conflicts.is_empty());
+        assert!(!check.solvable);
+        assert!(!check.is_clean());
+
+        // Repeating a given next to it is a conflict as well.
+        let mut board = puzzle.clone();
+        let (given, neighbor) = (CELLS[0], CELLS[2]);
+        board[neighbor] = puzzle[given].clone();
+        let check = puzzle.check(&board);
+        assert_eq!(check.mistakes, Some(vec![neighbor]));
+        assert_eq!(check.conflicts, vec![(given, neighbor)]);
+        assert!(!check.solvable);
+    }
+
+    #[test]
+    fn check_reports_no_mistakes_without_a_single_solution() {
+        let mut board = Grid::default();
+        board[CELLS[0]].set(DIGITS[0]);
+        let check = Grid::default().check(&board);
+        assert_eq!(check.mistakes, None);
+        assert!(check.solvable);
+        assert!(check.is_clean());
+    }
+}
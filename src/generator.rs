@@ -0,0 +1,330 @@
+use std::ops::RangeInclusive;
+use rand::prelude::*;
+use crate::{
+    below, search, shuffle, solve_logically, strategy::Logic, unit, Cell, Difficulty, Grid, GridState, Step, Technique, CELLS,
+    DIGITS,
+};
+
+// Puzzles are compared by difficulty, then by how little the singles alone eliminate.
+fn grade(puzzle: &Grid) -> Option<(Difficulty, u32)> {
+    let difficulty = puzzle.difficulty()?;
+    let singles = solve_logically(puzzle, &[Technique::HiddenSingle, Technique::NakedSingle]).state;
+    Some((difficulty, CELLS.iter().map(|&x| singles[x].options.count_ones()).sum()))
+}
+
+fn agrees(grid: &Grid, other: &Grid) -> bool {
+    CELLS.iter().all(|&x| grid[x].options == other[x].options)
+}
+
+const PATTERN_LIMIT: usize = 32;
+const ADJUSTMENTS: usize = 500;
+const SAMPLE_POOL: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Showcase {
+    pub puzzle: Grid,
+    pub state: Grid,
+    pub step: Step,
+}
+
+impl Grid {
+    pub fn generate<R: Rng>(rng: &mut R, difficulty: RangeInclusive<Difficulty>, attempts: usize) -> Option<Grid> {
+        (0..attempts).find_map(|_| {
+            let solution = Grid::solution(rng);
+            let puzzle = solution.dig_toward(rng, &difficulty);
+            match puzzle.difficulty() {
+                Some(rating) if difficulty.contains(&rating) => Some(puzzle),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn generate_showcase<R: Rng>(
+        rng: &mut R,
+        technique: Technique,
+        allowed: &[Technique],
+        attempts: usize,
+    ) -> Option<Showcase> {
+        let mut techniques: Vec<Technique> = allowed.iter().copied().filter(|&x| x != technique).collect();
+        techniques.push(technique);
+
+        (0..attempts).find_map(|_| {
+            let solution = Grid::solution(rng);
+            let puzzle = solution.dig(rng, |puzzle| {
+                let mut logic = Logic::new(puzzle);
+                logic.solve(&techniques);
+                logic.is_solved()
+            });
+            puzzle.showcase(technique, allowed)
+        })
+    }
+
+    pub fn generate_pattern<R: Rng>(rng: &mut R, pattern: u128, attempts: usize) -> Option<Grid> {
+        if pattern >> 81 != 0 {
+            return None;
+        }
+        let cells: Vec<Cell> = CELLS.iter().copied().filter(|&x| pattern & (1 << x.index()) != 0).collect();
+        if cells.is_empty() {
+            return None;
+        }
+
+        let mut puzzle = Grid::solution(rng);
+        for &cell in &CELLS {
+            if pattern & (1 << cell.index()) == 0 {
+                puzzle[cell].reset();
+            }
+        }
+        let mut count = puzzle.count_solutions(PATTERN_LIMIT);
+
+        for _ in 0..attempts {
+            if count == 1 {
+                return Some(puzzle);
+            }
+
+            let cell = cells[below(rng, cells.len() as u32) as usize];
+            let mut digits = DIGITS;
+            shuffle(rng, &mut digits);
+
+            for &digit in &digits {
+                let mut attempt = puzzle.clone();
+                if !attempt[cell].set(digit) {
+                    continue;
+                }
+
+                let attempt_count = attempt.count_solutions(PATTERN_LIMIT);
+                if attempt_count > 0 && attempt_count <= count {
+                    puzzle = attempt;
+                    count = attempt_count;
+                }
+            }
+        }
+
+        if count == 1 {
+            Some(puzzle)
+        } else {
+            None
+        }
+    }
+
+    pub fn showcase(&self, technique: Technique, allowed: &[Technique]) -> Option<Showcase> {
+        let mut techniques: Vec<Technique> = allowed.iter().copied().filter(|&x| x != technique).collect();
+
+        let mut logic = Logic::new(self);
+        logic.solve(&techniques);
+        if logic.is_solved() {
+            return None;
+        }
+
+        let step = logic.find(technique)?;
+        let state = logic.grid.clone();
+        logic.apply(&step);
+
+        techniques.push(technique);
+        logic.solve(&techniques);
+        if !logic.is_solved() {
+            return None;
+        }
+
+        Some(Showcase {
+            puzzle: self.clone(),
+            state,
+            step,
+        })
+    }
+
+    pub fn sample<R: Rng>(rng: &mut R, pool: usize) -> Grid {
+        // Importance resampling: each path is weighted by the inverse of its probability, so the
+        // selected grid tends to a uniform draw as the pool grows.
+        let mut chosen = None;
+        let mut total = 0f64;
+        let mut drawn = 0;
+
+        loop {
+            if drawn >= pool {
+                if let Some(grid) = chosen {
+                    return grid;
+                }
+            }
+            drawn += 1;
+
+            if let Some((grid, weight)) = Grid::sample_path(rng) {
+                total += weight;
+                if unit(rng) * total < weight {
+                    chosen = Some(grid);
+                }
+            }
+        }
+    }
+
+    fn sample_path<R: Rng>(rng: &mut R) -> Option<(Grid, f64)> {
+        let mut grid = Grid::default();
+        let mut weight = 1f64;
+
+        for &cell in &CELLS {
+            match grid.solve() {
+                GridState::Complete => break,
+                GridState::Impossible => return None,
+                GridState::Incomplete => (),
+            }
+
+            let count = grid[cell].options.count_ones();
+            if count < 2 {
+                continue;
+            }
+            let digit = grid[cell].iter().nth(below(rng, count) as usize)?;
+            grid[cell].set(digit);
+            weight *= f64::from(count);
+        }
+
+        match grid.solve() {
+            GridState::Complete => Some((grid, weight)),
+            _ => None,
+        }
+    }
+
+    // Plain bruteforce favours some grids over others, so puzzles start from the resampled grid.
+    pub(crate) fn solution<R: Rng>(rng: &mut R) -> Grid {
+        Grid::sample(rng, SAMPLE_POOL)
+    }
+
+    fn dig_toward<R: Rng>(&self, rng: &mut R, difficulty: &RangeInclusive<Difficulty>) -> Grid {
+        // While the puzzle is too easy, swap clues as long as it stays unique, within the band and
+        // at least as hard.
+        let mut puzzle = self.dig(rng, |puzzle| puzzle.difficulty().is_some_and(|x| x <= *difficulty.end()));
+        let mut current = match grade(&puzzle) {
+            Some(current) => current,
+            None => return puzzle,
+        };
+
+        for _ in 0..ADJUSTMENTS {
+            if current.0 >= *difficulty.start() {
+                break;
+            }
+
+            // Swapping in a cell where another solution disagrees tends to restore uniqueness.
+            let givens: Vec<Cell> = CELLS.iter().copied().filter(|&x| puzzle[x].state().is_defined()).collect();
+            let mut attempt = puzzle.clone();
+            attempt[givens[below(rng, givens.len() as u32) as usize]].reset();
+            let other = match attempt.solutions(2).into_iter().find(|x| !agrees(x, self)) {
+                Some(other) => other,
+                None => continue,
+            };
+            let differing: Vec<Cell> = CELLS.iter().copied().filter(|&x| other[x].options != self[x].options).collect();
+            let added = differing[below(rng, differing.len() as u32) as usize];
+            attempt[added] = self[added].clone();
+            if !attempt.is_unique() {
+                continue;
+            }
+
+            match grade(&attempt) {
+                Some(attempt_grade) if attempt_grade.0 <= *difficulty.end() && attempt_grade >= current => {
+                    puzzle = attempt;
+                    current = attempt_grade;
+                },
+                _ => (),
+            }
+        }
+
+        puzzle
+    }
+
+    pub(crate) fn dig<R, F>(&self, rng: &mut R, accept: F) -> Grid where
+        R: Rng,
+        F: Fn(&Grid) -> bool,
+    {
+        search::dig(self, rng, accept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+    use crate::{seed_rng, solve_logically, strategy::Logic, Difficulty, Grid, Technique, CELLS};
+
+    fn minirow(grid: &Grid, row: usize, column: usize) -> u16 {
+        (column..column + 3).fold(0, |set, x| set | grid[CELLS[row * 9 + x]].options)
+    }
+
+    fn minicolumn(grid: &Grid, row: usize, column: usize) -> u16 {
+        (row..row + 3).fold(0, |set, x| set | grid[CELLS[x * 9 + column]].options)
+    }
+
+    #[test]
+    fn generate_hits_every_difficulty() {
+        let cases = [(0, Difficulty::Easy), (1, Difficulty::Medium), (6, Difficulty::Hard), (5, Difficulty::Expert)];
+        for &(seed, difficulty) in &cases {
+            let puzzle = Grid::generate(&mut seed_rng(seed), difficulty..=difficulty, 20).unwrap();
+            assert_eq!(puzzle.difficulty(), Some(difficulty));
+            assert!(puzzle.is_unique());
+        }
+    }
+
+    #[test]
+    fn generate_stops_after_the_attempts() {
+        // No attempt draws nothing, and a band that no puzzle falls in gives up after as many
+        // draws as that many single attempts.
+        let mut rng = seed_rng(0);
+        assert!(Grid::generate(&mut rng, Difficulty::Easy..=Difficulty::Expert, 0).is_none());
+        assert_eq!(rng.next_u64(), seed_rng(0).next_u64());
+        let (mut once, mut thrice) = (seed_rng(0), seed_rng(0));
+        for _ in 0..3 {
+            assert!(Grid::generate(&mut once, Difficulty::Expert..=Difficulty::Easy, 1).is_none());
+        }
+        assert!(Grid::generate(&mut thrice, Difficulty::Expert..=Difficulty::Easy, 3).is_none());
+        assert_eq!(once.next_u64(), thrice.next_u64());
+    }
+
+    #[test]
+    fn showcase_stalls_until_the_technique() {
+        let allowed = [Technique::HiddenSingle, Technique::NakedSingle];
+        for &technique in &[Technique::Pointing, Technique::XWing] {
+            let showcase = Grid::generate_showcase(&mut seed_rng(1), technique, &allowed, 20).unwrap();
+            assert_eq!(showcase.step.technique, technique);
+            assert!(showcase.puzzle.is_unique());
+
+            // The allowed techniques stall on the state, and the step gets them going again.
+            assert!(solve_logically(&showcase.state, &allowed).steps.is_empty());
+            let mut logic = Logic::new(&showcase.state);
+            logic.apply(&showcase.step);
+            logic.solve(&allowed);
+            assert!(logic.is_solved());
+
+            // A puzzle that the singles finish has nothing to show.
+            let solution = showcase.puzzle.solutions(1).pop().unwrap();
+            assert!(solution.showcase(technique, &allowed).is_none());
+        }
+    }
+
+    #[test]
+    fn generate_pattern_rejects_cells_outside_the_grid() {
+        let mut rng = seed_rng(0);
+        assert!(Grid::generate_pattern(&mut rng, u128::MAX, 100).is_none());
+        assert!(Grid::generate_pattern(&mut rng, 1 << 81, 100).is_none());
+    }
+
+    #[test]
+    fn sample_finds_pure_bands_at_the_uniform_rate() {
+        // A band is pure when the second minirow of its first row matches the first minirow of
+        // another row. Over all grids this happens with probability 2/56; plain bruteforce gives
+        // nearly twice that.
+        const SAMPLES: usize = 300;
+        let mut rng = seed_rng(0);
+        let mut pure = 0;
+        for _ in 0..SAMPLES {
+            let grid = Grid::sample(&mut rng, 32);
+            for index in 0..3 {
+                let first = index * 3;
+                let set = minirow(&grid, first, 3);
+                pure += (set == minirow(&grid, first + 1, 0) || set == minirow(&grid, first + 2, 0)) as usize;
+                let set = minicolumn(&grid, 3, first);
+                pure += (set == minicolumn(&grid, 0, first + 1) || set == minicolumn(&grid, 0, first + 2)) as usize;
+            }
+        }
+
+        // Allow four standard deviations of the binomial around the expected count.
+        let trials = (SAMPLES * 6) as f64;
+        let p = 2.0 / 56.0;
+        let deviation = (trials * p * (1.0 - p)).sqrt();
+        assert!((pure as f64 - trials * p).abs() < 4.0 * deviation, "{} pure bands in {} trials", pure, trials);
+    }
+}
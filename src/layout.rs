@@ -0,0 +1,170 @@
+use std::{convert::TryFrom, fmt, str::FromStr};
+use crate::{Cell, BLOCKS, CELLS};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Layout([u8; 81]);
+
+impl Layout {
+    pub fn classic() -> Layout {
+        let mut regions = [0; 81];
+        for &block in &BLOCKS {
+            for cell in block {
+                regions[cell.index() as usize] = block.index();
+            }
+        }
+        Layout(regions)
+    }
+
+    pub fn region(&self, cell: Cell) -> u8 {
+        self.0[cell.index() as usize]
+    }
+
+    pub fn regions(&self) -> [[Cell; 9]; 9] {
+        let mut regions = [[CELLS[0]; 9]; 9];
+        let mut lengths = [0; 9];
+        for &cell in &CELLS {
+            let region = self.region(cell) as usize;
+            regions[region][lengths[region]] = cell;
+            lengths[region] += 1;
+        }
+        regions
+    }
+
+    fn is_connected(&self, region: u8) -> bool {
+        let mut stack: Vec<Cell> = CELLS.iter().copied().filter(|&x| self.region(x) == region).take(1).collect();
+        let mut seen = 0u128;
+        while let Some(cell) = stack.pop() {
+            if seen & 1 << cell.index() != 0 {
+                continue;
+            }
+            seen |= 1 << cell.index();
+
+            let (row, column) = (cell.row().index(), cell.column().index());
+            let adjacent = [
+                (row > 0, row.wrapping_sub(1), column),
+                (row < 8, row + 1, column),
+                (column > 0, row, column.wrapping_sub(1)),
+                (column < 8, row, column + 1),
+            ];
+            for &(inside, row, column) in &adjacent {
+                if inside && self.region(Cell::in_row(row, column)) == region {
+                    stack.push(Cell::in_row(row, column));
+                }
+            }
+        }
+        seen.count_ones() == 9
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::classic()
+    }
+}
+
+impl TryFrom<&[u8; 81]> for Layout {
+    type Error = &'static str;
+
+    fn try_from(value: &[u8; 81]) -> Result<Self, Self::Error> {
+        let mut sizes = [0; 9];
+        for &region in value.iter() {
+            if region >= 9 {
+                return Err("Invalid region");
+            }
+            sizes[region as usize] += 1;
+        }
+        if sizes.iter().any(|&x| x != 9) {
+            return Err("Regions must have nine cells");
+        }
+
+        let layout = Layout(*value);
+        if (0..9).any(|x| !layout.is_connected(x)) {
+            return Err("Regions must be connected");
+        }
+        Ok(layout)
+    }
+}
+
+impl FromStr for Layout {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut regions = [0; 81];
+        let mut len = 0;
+        for byte in s.bytes().filter(|x| !x.is_ascii_whitespace()) {
+            if len == 81 {
+                return Err("Too many cells");
+            }
+            regions[len] = match byte {
+                b'1'..=b'9' => byte - b'1',
+                _ => return Err("Invalid region"),
+            };
+            len += 1;
+        }
+        if len < 81 {
+            return Err("Too few cells");
+        }
+        Layout::try_from(&regions)
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, row) in self.0.chunks(9).enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            for &region in row {
+                write!(f, "{}", region + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{seed_rng, Layout, Variant};
+
+    const CLASSIC: &str = "111222333\n111222333\n111222333\n444555666\n444555666\n444555666\n777888999\n777888999\n777888999";
+    const JIGSAW: &str = "111222333\n111222333\n114222333\n144555666\n444555666\n444555666\n777888999\n777888999\n777888999";
+
+    #[test]
+    fn layouts_are_validated() {
+        assert_eq!(CLASSIC.parse::<Layout>(), Ok(Layout::classic()));
+        assert!(JIGSAW.parse::<Layout>().is_ok());
+
+        let mut text = CLASSIC.to_string();
+        text.replace_range(0..1, "2");
+        assert_eq!(text.parse::<Layout>(), Err("Regions must have nine cells"));
+
+        let mut text = CLASSIC.to_string();
+        text.replace_range(0..1, "9");
+        text.replace_range(text.len() - 1.., "1");
+        assert_eq!(text.parse::<Layout>(), Err("Regions must be connected"));
+
+        let mut text = CLASSIC.to_string();
+        text.replace_range(0..1, "0");
+        assert_eq!(text.parse::<Layout>(), Err("Invalid region"));
+        assert_eq!(CLASSIC[1..].parse::<Layout>(), Err("Too few cells"));
+        assert_eq!(format!("{}1", CLASSIC).parse::<Layout>(), Err("Too many cells"));
+    }
+
+    #[test]
+    fn jigsaw_text_round_trips_and_generates() {
+        let layout: Layout = JIGSAW.parse().unwrap();
+        assert_eq!(layout.to_string(), JIGSAW);
+        assert_eq!(layout.to_string().parse(), Ok(layout.clone()));
+
+        let variant = Variant::jigsaw(&layout);
+        let puzzle = variant.generate(&mut seed_rng(0)).unwrap();
+        assert!(variant.is_unique(&puzzle));
+
+        let solution = variant.solutions(&puzzle, 1).pop().unwrap();
+        for region in &layout.regions() {
+            let digits = region.iter().fold(0, |digits, &x| digits | solution[x].options);
+            assert_eq!(digits, (1 << 9) - 1);
+        }
+    }
+}
@@ -7,6 +7,34 @@ use std::{
 };
 use rand::prelude::*;
 
+mod board;
+mod cage;
+mod check;
+mod generator;
+mod layout;
+mod search;
+mod seed;
+mod strategy;
+mod symmetry;
+mod transform;
+mod variant;
+
+use search::{Constraints, Member, Options, Search};
+
+pub use board::{Board, Board12, Board16, Board25, Board4, Board6, Board9};
+pub use cage::Cage;
+pub use check::Check;
+pub use generator::Showcase;
+pub use layout::Layout;
+pub use seed::{date_seed, seed_rng, SeedRng};
+pub use strategy::{
+    explain_exclusion, solve_logically, Difficulty, DifficultyProfile, Exclusion, Hint, LogicalSolution, NotExcluded, Rating,
+    Step, Technique, Walkthrough, WalkthroughStyle, TECHNIQUES,
+};
+pub use symmetry::{Symmetry, SYMMETRIES};
+pub use transform::{are_equivalent, Transform};
+pub use variant::Variant;
+
 macro_rules! cells {
     ($($a:ident)*) => {
         cells!(@rows ($($a)*) -> (0 1 2 3 4 5 6 7 8))
@@ -45,6 +73,14 @@ pub struct Cell(u8);
 
 pub const CELLS: [Cell; 81] = cells!(cell);
 
+const fn bit(cell: Cell) -> u128 {
+    1 << cell.0
+}
+
+fn cells(mask: u128) -> impl Iterator<Item = Cell> {
+    CELLS.iter().copied().filter(move |&x| mask & bit(x) != 0)
+}
+
 impl Cell {
     const NEIGHBORS: [[Cell; 20]; 81] = cells!(neighbors);
 
@@ -125,7 +161,7 @@ pub const DIGITS: [Digit; 9] = [Digit::One, Digit::Two, Digit::Three, Digit::Fou
 
 impl From<Digit> for u8 {
     fn from(value: Digit) -> Self {
-        Self::from((value as u8) + 1)
+        (value as u8) + 1
     }
 }
 
@@ -155,24 +191,15 @@ pub enum ValueState {
 
 impl ValueState {
     pub fn is_defined(self) -> bool {
-        match self {
-            ValueState::Defined(_) => true,
-            _ => false,
-        }
+        matches!(self, ValueState::Defined(_))
     }
 
     pub fn is_undefined(self) -> bool {
-        match self {
-            ValueState::Undefined => true,
-            _ => false,
-        }
+        matches!(self, ValueState::Undefined)
     }
 
     pub fn is_impossible(self) -> bool {
-        match self {
-            ValueState::Impossible => true,
-            _ => false,
-        }
+        matches!(self, ValueState::Impossible)
     }
 
     pub fn digit(self) -> Option<Digit> {
@@ -302,7 +329,7 @@ impl iter::IntoIterator for Group {
     type IntoIter = iter::Copied<slice::Iter<'static, Cell>>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.cells().into_iter().copied()
+        self.cells().iter().copied()
     }
 }
 
@@ -316,24 +343,28 @@ pub enum GridState {
 #[derive(Debug, Clone)]
 pub struct Grid([Value; 81]);
 
-fn partial_shuffle<'a, R, T, F>(rng: &mut R, items: &'a mut [T], pred: F) -> &'a[T] where
-    R: Rng,
-    T: Copy,
-    F: Fn(T) -> bool,
-{
-    let mut len = 0usize;
-    for index in 0..items.len() {
-        let item = items[index];
-        if !pred(item) {
-            continue;
-        }
-        if index > len {
-            items[len] = item;
+const REPAIR_SAMPLE: usize = 64;
+
+fn below<R: Rng>(rng: &mut R, bound: u32) -> u32 {
+    let zone = u32::MAX - u32::MAX % bound;
+    loop {
+        let value = rng.next_u32();
+        if value < zone {
+            return value % bound;
         }
-        len += 1;
     }
-    &mut items[..len].shuffle(rng);
-    &items[..len]
+}
+
+// Takes the top 53 bits by hand, like `below`, so seeded output does not depend on how rand builds
+// floats.
+fn unit<R: Rng>(rng: &mut R) -> f64 {
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn shuffle<R: Rng, T>(rng: &mut R, items: &mut [T]) {
+    for index in (1..items.len()).rev() {
+        items.swap(index, below(rng, index as u32 + 1) as usize);
+    }
 }
 
 impl Grid {
@@ -350,37 +381,109 @@ impl Grid {
     }
 
     pub fn solve(&mut self) -> GridState {
-        Solver::new(self).solve()
+        search::propagate(&Classic, self)
     }
 
     pub fn bruteforce<R: Rng>(&mut self, rng: &mut R) -> bool {
-        match self.solve() {
-            GridState::Complete => return true,
-            GridState::Impossible => return false,
-            GridState::Incomplete => (),
+        search::bruteforce(self, rng)
+    }
+
+    pub fn count_solutions(&self, limit: usize) -> usize {
+        search::count_solutions(self, limit)
+    }
+
+    pub fn solutions(&self, limit: usize) -> Vec<Grid> {
+        search::solutions(self, limit)
+    }
+
+    pub fn is_unique(&self) -> bool {
+        search::is_unique(self)
+    }
+
+    pub fn is_minimal(&self) -> bool {
+        self.is_unique() && !CELLS.iter().any(|&x| self.is_redundant(x))
+    }
+
+    pub fn redundant_clues(&self) -> Vec<Cell> {
+        if !self.is_unique() {
+            return Vec::new();
         }
+        CELLS.iter().copied().filter(|&x| self.is_redundant(x)).collect()
+    }
 
-        let mut undefined = CELLS;
-        let undefined = partial_shuffle(rng, &mut undefined, |x| self[x].state().is_undefined());
+    pub fn repairs(&self, count: usize) -> Vec<Vec<(Cell, Digit)>> {
+        let solutions = self.solutions(REPAIR_SAMPLE);
+        if solutions.len() < 2 {
+            return Vec::new();
+        }
 
-        for &cell in undefined {
-            let value = &self[cell];
+        let mut repairs: Vec<Vec<(Cell, Digit)>> = Vec::new();
+        for reference in solutions.iter().take(count) {
+            let repair = self.repair(reference, &solutions);
+            if !repairs.contains(&repair) {
+                repairs.push(repair);
+            }
+        }
+        repairs.sort_by_key(|x| x.len());
+        repairs
+    }
 
-            let mut candidates = DIGITS;
-            let candidates = partial_shuffle(rng, &mut candidates, |x| value.has_option(x));
+    fn repair(&self, reference: &Grid, solutions: &[Grid]) -> Vec<(Cell, Digit)> {
+        let mut others: Vec<Grid> = solutions.iter()
+            .filter(|x| !x.agrees(reference, &CELLS))
+            .cloned()
+            .collect();
+        let mut cells: Vec<Cell> = Vec::new();
 
-            for &digit in candidates {
-                let mut attempt = self.clone();
-                attempt[cell].set(digit);
+        loop {
+            others.retain(|x| x.agrees(reference, &cells));
 
-                if attempt.bruteforce(rng) {
-                    *self = attempt;
-                    return true;
+            if others.is_empty() {
+                let puzzle = self.with_clues(reference, &cells);
+                match puzzle.solutions(2).into_iter().find(|x| !x.agrees(reference, &CELLS)) {
+                    Some(other) => others.push(other),
+                    None => break,
                 }
             }
+
+            let cell = CELLS.iter().copied()
+                .filter(|&x| !self[x].state().is_defined() && !cells.contains(&x))
+                .max_by_key(|&x| others.iter().filter(|other| !other.agrees(reference, &[x])).count())
+                .unwrap();
+            cells.push(cell);
+        }
+
+        for index in (0..cells.len()).rev() {
+            let mut attempt = cells.clone();
+            attempt.remove(index);
+            if self.with_clues(reference, &attempt).is_unique() {
+                cells = attempt;
+            }
+        }
+
+        cells.sort_by_key(|x| x.index());
+        cells.into_iter().filter_map(|x| Some((x, reference[x].state().digit()?))).collect()
+    }
+
+    fn agrees(&self, other: &Grid, cells: &[Cell]) -> bool {
+        cells.iter().all(|&x| self[x].options == other[x].options)
+    }
+
+    fn with_clues(&self, solution: &Grid, cells: &[Cell]) -> Grid {
+        let mut puzzle = self.clone();
+        for &cell in cells {
+            puzzle[cell] = solution[cell].clone();
         }
+        puzzle
+    }
 
-        false
+    fn is_redundant(&self, cell: Cell) -> bool {
+        if !self[cell].state().is_defined() {
+            return false;
+        }
+        let mut attempt = self.clone();
+        attempt[cell].reset();
+        attempt.is_unique()
     }
 }
 
@@ -404,6 +507,28 @@ impl Default for Grid {
     }
 }
 
+impl Search for Grid {
+    fn size(&self) -> usize {
+        81
+    }
+
+    fn propagate(&mut self) -> GridState {
+        self.solve()
+    }
+
+    fn candidates(&self, cell: usize) -> u32 {
+        u32::from(self.0[cell].options)
+    }
+
+    fn assign(&mut self, cell: usize, digit: u32) {
+        self.0[cell].options = 1 << digit;
+    }
+
+    fn clear(&mut self, cell: usize) {
+        self.0[cell].reset();
+    }
+}
+
 impl TryFrom<&[u8; 81]> for Grid {
     type Error = &'static str;
 
@@ -422,156 +547,138 @@ impl fmt::Display for Grid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for &row in &ROWS {
             if row.index() % 3 == 0 {
-                write!(f, "+-----------+-----------+-----------+\n")?;
+                writeln!(f, "+-----------+-----------+-----------+")?;
             } else {
-                write!(f, "|           |           |           |\n")?;
+                writeln!(f, "|           |           |           |")?;
             }
             for (column, cell) in row.into_iter().enumerate() {
                 write!(f, "{} {} ", if column % 3 == 0 { '|' } else { ' ' }, self[cell])?;
             }
-            write!(f, "|\n")?;
+            writeln!(f, "|")?;
         }
         write!(f, "+-----------+-----------+-----------+")
     }
 }
 
-struct Solver<'a> {
-    grid: &'a mut Grid,
-    queue: u128,
-    done: u128,
-}
+pub struct Candidates<'a>(&'a Grid);
 
-impl<'a> Solver<'a> {
-    fn new(grid: &mut Grid) -> Solver {
-        Solver {
-            grid,
-            queue: (1 << 108) - 1,
-            done: 0,
-        }
+impl Grid {
+    pub fn candidates(&self) -> Candidates<'_> {
+        Candidates(self)
     }
+}
 
-    fn solve(&mut self) -> GridState {
-        'consumer: loop {
-            let mut mask = 1u128;
-
-            for &cell in &CELLS {
-                if self.queue & mask != 0 {
-                    self.queue &= !mask;
-                    if !self.propagate_cell(cell, mask) {
-                        return GridState::Impossible;
-                    }
-                    if self.queue == 0 {
-                        break 'consumer;
-                    }
-                }
-                mask <<= 1;
+impl fmt::Display for Candidates<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &row in &ROWS {
+            if row.index() % 3 == 0 {
+                writeln!(f, "+-----------------+-----------------+-----------------+")?;
+            } else {
+                writeln!(f, "|                 |                 |                 |")?;
             }
-
-            for &groups in &[&ROWS, &COLUMNS, &BLOCKS] {
-                for &group in groups {
-                    if self.queue & mask != 0 {
-                        self.queue &= !mask;
-                        if !self.resolve_group(group, mask) {
-                            return GridState::Impossible;
-                        }
-                        if self.queue == 0 {
-                            break 'consumer;
+            for line in DIGITS.chunks(3) {
+                for (column, cell) in row.into_iter().enumerate() {
+                    write!(f, "{} ", if column % 3 == 0 { '|' } else { ' ' })?;
+                    let value = &self.0[cell];
+                    for &digit in line {
+                        match value.state() {
+                            ValueState::Defined(defined) if digit == Digit::Five => write!(f, "{}", defined)?,
+                            ValueState::Defined(_) => write!(f, " ")?,
+                            _ if value.has_option(digit) => write!(f, "{}", digit)?,
+                            _ => write!(f, ".")?,
                         }
                     }
-                    mask <<= 1;
+                    write!(f, " ")?;
                 }
+                writeln!(f, "|")?;
             }
-        };
-
-        if self.done == (1 << 108) - 1 {
-            GridState::Complete
-        } else {
-            GridState::Incomplete
         }
+        write!(f, "+-----------------+-----------------+-----------------+")
     }
+}
 
-    fn propagate_cell(&mut self, cell: Cell, mask: u128) -> bool {
-        match self.grid[cell].state() {
-            ValueState::Defined(digit) => {
-                for &neighbor in cell.neighbors() {
-                    if !self.grid[neighbor].remove_option(digit) {
-                        continue;
-                    }
+// The rows, columns and blocks of a classic grid.
+struct Classic;
 
-                    self.enqueue_cell(neighbor);
+impl Constraints for Classic {
+    type Member = Cell;
 
-                    for &group in &[neighbor.row(), neighbor.column(), neighbor.block()] {
-                        self.enqueue_group(group);
-                    }
-                }
+    fn digits(&self) -> u32 {
+        (1 << 9) - 1
+    }
 
-                self.done |= mask;
+    fn houses(&self) -> usize {
+        27
+    }
 
-                true
-            },
-            ValueState::Undefined => true,
-            ValueState::Impossible => false,
+    fn house(&self, index: usize) -> &[Cell] {
+        match index {
+            0..=8 => Group::ROWS[index].as_ref(),
+            9..=17 => Group::COLUMNS[index - 9].as_ref(),
+            _ => Group::BLOCKS[index - 18].as_ref(),
         }
     }
 
-    fn resolve_group(&mut self, group: Group, mask: u128) -> bool {
-        let mut defined = 0u8;
+    fn peers<F: FnMut(usize)>(&self, cell: usize, mut visit: F) {
+        for &neighbor in CELLS[cell].neighbors() {
+            visit(neighbor.0 as usize);
+        }
+    }
+}
 
-        for &digit in &DIGITS {
-            let mut canditates = group.into_iter().filter(|&x| self.grid[x].has_option(digit));
-            match canditates.next() {
-                Some(cell) => {
-                    if canditates.next().is_some() {
-                        continue;
-                    }
+impl Member for Cell {
+    fn offset(self) -> usize {
+        self.0 as usize
+    }
+}
 
-                    defined += 1;
+impl Options for Grid {
+    fn len(&self) -> usize {
+        81
+    }
 
-                    if !self.grid[cell].set(digit) {
-                        continue;
-                    }
+    fn get(&self, cell: usize) -> u32 {
+        u32::from(self.0[cell].options)
+    }
 
-                    self.enqueue_cell(cell);
+    fn set(&mut self, cell: usize, options: u32) {
+        self.0[cell].options = options as u16;
+    }
+}
 
-                    for &group in &[cell.row(), cell.column(), cell.block()] {
-                        self.enqueue_group(group);
-                    }
-                },
-                None => {
-                    for cell in group {
-                        let value = &mut self.grid[cell];
-                        if value.state().is_undefined() {
-                            value.empty();
-                        }
-                    }
-                    return false;
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use crate::{Board9, Grid};
 
-        if defined == 9 {
-            self.queue &= !mask;
-            self.done |= mask;
-        }
+    const PUZZLE: &str = "42....6...6.1....7..5..4....1...7.8...2..1.96..892.......3.2..1.8.....3..4.7.....";
 
-        true
+    fn grid(s: &str) -> Grid {
+        Grid::from(&s.parse::<Board9>().unwrap())
     }
 
-    fn enqueue_cell(&mut self, cell: Cell) {
-        let mask = 1u128 << cell.0;
-        if self.done & mask == 0 {
-            self.queue |= mask;
-        }
-    }
+    #[test]
+    fn repairs_make_the_puzzle_unique() {
+        assert!(grid(PUZZLE).repairs(5).is_empty());
 
-    fn enqueue_group(&mut self, group: Group) {
-        let mask = 1u128 << match group {
-            Group::Row(index) => 81 + index,
-            Group::Column(index) => 90 + index,
-            Group::Block(index) => 99 + index,
-        };
-        if self.done & mask == 0 {
-            self.queue |= mask;
+        // Without its first clue the puzzle has many solutions.
+        let puzzle = grid(&format!(".{}", &PUZZLE[1..]));
+        let repairs = puzzle.repairs(5);
+        assert_eq!(repairs.len(), 5);
+        assert!(repairs.windows(2).all(|x| x[0].len() <= x[1].len()));
+        assert!(repairs.iter().enumerate().all(|(index, x)| !repairs[..index].contains(x)));
+        for repair in &repairs {
+            let mut repaired = puzzle.clone();
+            for &(cell, digit) in repair {
+                assert!(repaired[cell].set(digit));
+            }
+            assert!(repaired.is_unique());
+
+            // Every added clue is needed.
+            for &(cell, _) in repair {
+                let mut attempt = repaired.clone();
+                attempt[cell].reset();
+                assert!(!attempt.is_unique());
+            }
         }
     }
 }
@@ -141,4 +141,8 @@ fn main() {
     grid = sudoku::Grid::default();
     let state = grid.bruteforce(&mut rng);
     println!("{}\n{:?} / {:?}", grid, state, grid.state());
+
+    if let Some(grid) = sudoku::Grid::generate(&mut rng, sudoku::Difficulty::Medium..=sudoku::Difficulty::Hard, 100) {
+        println!("{}\n{:?}", grid, grid.difficulty());
+    }
 }
@@ -0,0 +1,262 @@
+use rand::Rng;
+use crate::{below, shuffle, GridState};
+
+// The largest board has 25 x 25 cells.
+pub(crate) const MAX_CELLS: usize = 625;
+
+// Backtracking shared by every board shape. Implementors propagate constraints and expose the
+// candidates of each cell as a bit mask.
+pub(crate) trait Search: Clone {
+    fn size(&self) -> usize;
+    fn propagate(&mut self) -> GridState;
+    fn candidates(&self, cell: usize) -> u32;
+    fn assign(&mut self, cell: usize, digit: u32);
+    fn clear(&mut self, cell: usize);
+}
+
+// The houses that hold every digit once and the cells that cannot share a digit with each cell.
+pub(crate) trait Constraints {
+    type Member: Member;
+    fn digits(&self) -> u32;
+    fn houses(&self) -> usize;
+    fn house(&self, index: usize) -> &[Self::Member];
+    fn peers<F: FnMut(usize)>(&self, cell: usize, visit: F);
+}
+
+pub(crate) trait Member: Copy {
+    fn offset(self) -> usize;
+}
+
+impl Member for u16 {
+    fn offset(self) -> usize {
+        self as usize
+    }
+}
+
+// The candidates of every cell as bit masks.
+pub(crate) trait Options {
+    fn len(&self) -> usize;
+    fn get(&self, cell: usize) -> u32;
+    fn set(&mut self, cell: usize, options: u32);
+}
+
+impl Options for [u32] {
+    fn len(&self) -> usize {
+        <[u32]>::len(self)
+    }
+
+    fn get(&self, cell: usize) -> u32 {
+        self[cell]
+    }
+
+    fn set(&mut self, cell: usize, options: u32) {
+        self[cell] = options;
+    }
+}
+
+// Removes the digit of every solved cell from its peers and places the digits that fit in a single
+// cell of a house, until neither finds anything new.
+pub(crate) fn propagate<C, S>(constraints: &C, candidates: &mut S) -> GridState where
+    C: Constraints,
+    S: Options + ?Sized,
+{
+    let mut done = [false; MAX_CELLS];
+    loop {
+        let mut changed = false;
+
+        let len = candidates.len();
+        for (cell, done) in done[..len].iter_mut().enumerate() {
+            let options = candidates.get(cell);
+            if options == 0 {
+                return GridState::Impossible;
+            }
+            if *done || !options.is_power_of_two() {
+                continue;
+            }
+            *done = true;
+            changed = true;
+
+            constraints.peers(cell, |peer| {
+                let remaining = candidates.get(peer) & !options;
+                candidates.set(peer, remaining);
+            });
+        }
+
+        for house in 0..constraints.houses() {
+            let members = constraints.house(house);
+            let mut once = 0;
+            let mut twice = 0;
+            for &member in members {
+                let options = candidates.get(member.offset());
+                twice |= once & options;
+                once |= options;
+            }
+            if once != constraints.digits() {
+                return GridState::Impossible;
+            }
+
+            let singles = once & !twice;
+            for &member in members {
+                let options = candidates.get(member.offset());
+                let single = options & singles;
+                if single == 0 {
+                    continue;
+                }
+                // A cell cannot be the only place for two digits.
+                if !single.is_power_of_two() {
+                    return GridState::Impossible;
+                }
+                if single == options {
+                    continue;
+                }
+                candidates.set(member.offset(), single);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return if (0..candidates.len()).all(|x| candidates.get(x).is_power_of_two()) {
+                GridState::Complete
+            } else {
+                GridState::Incomplete
+            };
+        }
+    }
+}
+
+fn nth_bit(mask: u32, n: u32) -> u32 {
+    let mut mask = mask;
+    for _ in 0..n {
+        mask &= mask - 1;
+    }
+    mask.trailing_zeros()
+}
+
+// Branches on the cell with the fewest candidates, which keeps the search tree small.
+fn branch<S: Search>(puzzle: &S) -> Option<usize> {
+    (0..puzzle.size())
+        .filter(|&x| puzzle.candidates(x).count_ones() > 1)
+        .min_by_key(|&x| puzzle.candidates(x).count_ones())
+}
+
+pub(crate) fn search<S: Search, F: FnMut(&S) -> bool>(puzzle: &S, visit: &mut F) -> bool {
+    let mut puzzle = puzzle.clone();
+    match puzzle.propagate() {
+        GridState::Complete => return visit(&puzzle),
+        GridState::Impossible => return true,
+        GridState::Incomplete => (),
+    }
+
+    let cell = match branch(&puzzle) {
+        Some(cell) => cell,
+        None => return true,
+    };
+    let options = puzzle.candidates(cell);
+    for digit in 0..32 {
+        if options & 1 << digit == 0 {
+            continue;
+        }
+        let mut attempt = puzzle.clone();
+        attempt.assign(cell, digit);
+
+        if !search(&attempt, visit) {
+            return false;
+        }
+    }
+    true
+}
+
+pub(crate) fn count_solutions<S: Search>(puzzle: &S, limit: usize) -> usize {
+    let mut count = 0;
+    if limit > 0 {
+        search(puzzle, &mut |_| {
+            count += 1;
+            count < limit
+        });
+    }
+    count
+}
+
+pub(crate) fn solutions<S: Search>(puzzle: &S, limit: usize) -> Vec<S> {
+    let mut solutions = Vec::new();
+    if limit > 0 {
+        search(puzzle, &mut |solution| {
+            solutions.push(solution.clone());
+            solutions.len() < limit
+        });
+    }
+    solutions
+}
+
+pub(crate) fn is_unique<S: Search>(puzzle: &S) -> bool {
+    count_solutions(puzzle, 2) == 1
+}
+
+pub(crate) fn bruteforce<S: Search, R: Rng>(puzzle: &mut S, rng: &mut R) -> bool {
+    match puzzle.propagate() {
+        GridState::Complete => return true,
+        GridState::Impossible => return false,
+        GridState::Incomplete => (),
+    }
+
+    // Ties between the most constrained cells are broken uniformly at random.
+    let mut cell = None;
+    let mut fewest = u32::MAX;
+    let mut ties = 0;
+    for index in 0..puzzle.size() {
+        let count = puzzle.candidates(index).count_ones();
+        if count < 2 || count > fewest {
+            continue;
+        }
+        if count < fewest {
+            fewest = count;
+            ties = 0;
+        }
+        ties += 1;
+        if below(rng, ties) == 0 {
+            cell = Some(index);
+        }
+    }
+    let cell = match cell {
+        Some(cell) => cell,
+        None => return false,
+    };
+
+    let mut options = puzzle.candidates(cell);
+    while options != 0 {
+        let digit = nth_bit(options, below(rng, options.count_ones()));
+        options &= !(1 << digit);
+
+        let mut attempt = puzzle.clone();
+        attempt.assign(cell, digit);
+        if bruteforce(&mut attempt, rng) {
+            *puzzle = attempt;
+            return true;
+        }
+    }
+    false
+}
+
+pub(crate) fn dig<S, R, F>(puzzle: &S, rng: &mut R, accept: F) -> S where
+    S: Search,
+    R: Rng,
+    F: Fn(&S) -> bool,
+{
+    let mut order = [0u16; MAX_CELLS];
+    let order = &mut order[..puzzle.size()];
+    for (index, cell) in order.iter_mut().enumerate() {
+        *cell = index as u16;
+    }
+    shuffle(rng, order);
+
+    let mut puzzle = puzzle.clone();
+    for &cell in order.iter() {
+        let mut attempt = puzzle.clone();
+        attempt.clear(cell as usize);
+
+        if is_unique(&attempt) && accept(&attempt) {
+            puzzle = attempt;
+        }
+    }
+    puzzle
+}
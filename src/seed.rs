@@ -0,0 +1,75 @@
+use std::ops::RangeInclusive;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use crate::{Difficulty, Grid};
+
+pub type SeedRng = ChaCha8Rng;
+
+pub fn seed_rng(seed: u64) -> SeedRng {
+    let mut bytes = [0u8; 32];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    SeedRng::from_seed(bytes)
+}
+
+impl Grid {
+    pub fn from_seed(seed: u64, difficulty: RangeInclusive<Difficulty>, attempts: usize) -> Option<Grid> {
+        Grid::generate(&mut seed_rng(seed), difficulty, attempts)
+    }
+
+    // Only an invalid date fails: the generator keeps drawing from the same stream until it hits
+    // the requested difficulty.
+    pub fn daily(year: u16, month: u8, day: u8, difficulty: Difficulty) -> Option<Grid> {
+        let seed = date_seed(year, month, day)?;
+        let mut rng = seed_rng(seed * 4 + difficulty as u64);
+        loop {
+            if let Some(puzzle) = Grid::generate(&mut rng, difficulty..=difficulty, 1) {
+                return Some(puzzle);
+            }
+        }
+    }
+}
+
+pub fn date_seed(year: u16, month: u8, day: u8) -> Option<u64> {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if day == 0 || day > days {
+        return None;
+    }
+    Some(u64::from(year) * 10000 + u64::from(month) * 100 + u64::from(day))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Difficulty, Grid, CELLS};
+
+    fn givens(grid: &Grid) -> String {
+        CELLS.iter().map(|&x| grid[x].state().digit().map_or('.', |x| char::from(b'0' + u8::from(x)))).collect()
+    }
+
+    // Seeds are shared between players, so the same seed must keep producing the same puzzle.
+    #[test]
+    fn from_seed_is_stable() {
+        let puzzles = [
+            (1, Difficulty::Easy, "..3......42.....81.....892.9...2.......6.1....3.9....7.12..56.8.....7.....5...27."),
+            (2, Difficulty::Medium, "42....6...6.1....7..5..4....1...7.8...2..1.96..892.......3.2..1.8.....3..4.7....."),
+            (3, Difficulty::Hard, "......94.3......6.9.5......2....4.39.5..2..811..3.......65....8...7.8.....1..6.93"),
+        ];
+        for &(seed, difficulty, expected) in &puzzles {
+            let puzzle = Grid::from_seed(seed, difficulty..=difficulty, 20).unwrap();
+            assert_eq!(givens(&puzzle), expected);
+        }
+    }
+
+    #[test]
+    fn daily_only_fails_on_invalid_dates() {
+        let puzzle = Grid::daily(2024, 2, 29, Difficulty::Expert).unwrap();
+        assert_eq!(givens(&puzzle), "...2..9.3..3.1......78...1....7....8....9.4...5....27.6.1....424.5..6..93.......6");
+        assert!(Grid::daily(2023, 2, 29, Difficulty::Expert).is_none());
+    }
+}
@@ -0,0 +1,1156 @@
+use std::{
+    fmt,
+    iter::{self, Iterator},
+    ops::Range,
+    rc::Rc,
+};
+use crate::{bit, cage::Sum, cells, Cell, Digit, Grid, Group, ValueState, BLOCKS, CELLS, COLUMNS, DIGITS, ROWS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+            Difficulty::Expert => "Expert",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Technique {
+    HiddenSingle,
+    NakedSingle,
+    CageCombination,
+    Pointing,
+    Claiming,
+    RuleOf45,
+    NakedPair,
+    XWing,
+    HiddenPair,
+    NakedTriple,
+    Swordfish,
+    HiddenTriple,
+    XYWing,
+    XYZWing,
+    NakedQuad,
+    Jellyfish,
+    HiddenQuad,
+    Contradiction,
+}
+
+// Contradiction is trial and error rather than a pattern, so it is left out of the default set.
+pub const TECHNIQUES: [Technique; 17] = [
+    Technique::HiddenSingle,
+    Technique::NakedSingle,
+    Technique::CageCombination,
+    Technique::Pointing,
+    Technique::Claiming,
+    Technique::RuleOf45,
+    Technique::NakedPair,
+    Technique::XWing,
+    Technique::HiddenPair,
+    Technique::NakedTriple,
+    Technique::Swordfish,
+    Technique::HiddenTriple,
+    Technique::XYWing,
+    Technique::XYZWing,
+    Technique::NakedQuad,
+    Technique::Jellyfish,
+    Technique::HiddenQuad,
+];
+
+impl Technique {
+    pub const fn difficulty(self) -> Difficulty {
+        match self {
+            Technique::HiddenSingle | Technique::NakedSingle => Difficulty::Easy,
+            Technique::CageCombination | Technique::Pointing | Technique::Claiming | Technique::RuleOf45 => Difficulty::Medium,
+            Technique::NakedPair | Technique::HiddenPair => Difficulty::Medium,
+            Technique::XWing | Technique::NakedTriple | Technique::Swordfish | Technique::HiddenTriple => Difficulty::Hard,
+            Technique::XYWing | Technique::XYZWing | Technique::NakedQuad | Technique::Jellyfish | Technique::HiddenQuad => Difficulty::Expert,
+            Technique::Contradiction => Difficulty::Expert,
+        }
+    }
+
+    pub const fn rating(self) -> f32 {
+        match self {
+            Technique::HiddenSingle => 1.5,
+            Technique::NakedSingle => 2.3,
+            Technique::CageCombination => 2.5,
+            Technique::Pointing => 2.6,
+            Technique::Claiming => 2.8,
+            Technique::RuleOf45 => 2.9,
+            Technique::NakedPair => 3.0,
+            Technique::XWing => 3.2,
+            Technique::HiddenPair => 3.4,
+            Technique::NakedTriple => 3.6,
+            Technique::Swordfish => 3.8,
+            Technique::HiddenTriple => 4.0,
+            Technique::XYWing => 4.2,
+            Technique::XYZWing => 4.4,
+            Technique::NakedQuad => 5.0,
+            Technique::Jellyfish => 5.2,
+            Technique::HiddenQuad => 5.4,
+            Technique::Contradiction => 7.5,
+        }
+    }
+}
+
+impl fmt::Display for Technique {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Technique::HiddenSingle => "Hidden Single",
+            Technique::NakedSingle => "Naked Single",
+            Technique::CageCombination => "Cage Combination",
+            Technique::Pointing => "Pointing",
+            Technique::Claiming => "Claiming",
+            Technique::RuleOf45 => "Rule of 45",
+            Technique::NakedPair => "Naked Pair",
+            Technique::XWing => "X-Wing",
+            Technique::HiddenPair => "Hidden Pair",
+            Technique::NakedTriple => "Naked Triple",
+            Technique::Swordfish => "Swordfish",
+            Technique::HiddenTriple => "Hidden Triple",
+            Technique::XYWing => "XY-Wing",
+            Technique::XYZWing => "XYZ-Wing",
+            Technique::NakedQuad => "Naked Quad",
+            Technique::Jellyfish => "Jellyfish",
+            Technique::HiddenQuad => "Hidden Quad",
+            Technique::Contradiction => "Contradiction",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub technique: Technique,
+    pub groups: Vec<Group>,
+    pub cells: Vec<Cell>,
+    pub digits: Vec<Digit>,
+    pub placement: Option<(Cell, Digit)>,
+    pub eliminations: Vec<(Cell, Digit)>,
+}
+
+impl Step {
+    pub fn rating(&self) -> f32 {
+        match (self.technique, self.groups.first()) {
+            (Technique::HiddenSingle, Some(Group::Block(_))) => 1.2,
+            (technique, _) => technique.rating(),
+        }
+    }
+
+    fn placement(technique: Technique, groups: Vec<Group>, cell: Cell, digit: Digit) -> Step {
+        Step {
+            technique,
+            groups,
+            cells: vec![cell],
+            digits: vec![digit],
+            placement: Some((cell, digit)),
+            eliminations: Vec::new(),
+        }
+    }
+
+    fn elimination(
+        technique: Technique,
+        groups: Vec<Group>,
+        cells: Vec<Cell>,
+        digits: Vec<Digit>,
+        eliminations: Vec<(Cell, Digit)>,
+    ) -> Option<Step> {
+        if eliminations.is_empty() {
+            return None;
+        }
+        Some(Step {
+            technique,
+            groups,
+            cells,
+            digits,
+            placement: None,
+            eliminations,
+        })
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.technique)?;
+
+        let half = self.groups.len() / 2;
+        match self.technique {
+            Technique::HiddenSingle | Technique::NakedSingle => {
+                if let Some((cell, digit)) = self.placement {
+                    write!(f, "{} = {}", Name(cell), digit)?;
+                }
+                if !self.groups.is_empty() {
+                    f.write_str(" in ")?;
+                    names(f, &self.groups)?;
+                }
+            },
+            Technique::Pointing | Technique::Claiming | Technique::XWing | Technique::Swordfish | Technique::Jellyfish => {
+                join(f, self.digits.iter())?;
+                if self.groups.is_empty() {
+                    f.write_str(" is confined to ")?;
+                    join(f, self.cells.iter().map(|&x| Name(x)))?;
+                } else {
+                    f.write_str(" in ")?;
+                    names(f, &self.groups[..half])?;
+                    f.write_str(" is confined to ")?;
+                    names(f, &self.groups[half..])?;
+                }
+            },
+            Technique::NakedPair | Technique::NakedTriple | Technique::NakedQuad => {
+                join(f, self.cells.iter().map(|&x| Name(x)))?;
+                f.write_str(" only hold ")?;
+                join(f, self.digits.iter())?;
+                if !self.groups.is_empty() {
+                    f.write_str(" in ")?;
+                    names(f, &self.groups)?;
+                }
+            },
+            Technique::HiddenPair | Technique::HiddenTriple | Technique::HiddenQuad => {
+                join(f, self.digits.iter())?;
+                f.write_str(" only fit in ")?;
+                join(f, self.cells.iter().map(|&x| Name(x)))?;
+                if !self.groups.is_empty() {
+                    f.write_str(" in ")?;
+                    names(f, &self.groups)?;
+                }
+            },
+            Technique::CageCombination | Technique::RuleOf45 => {
+                join(f, self.cells.iter().map(|&x| Name(x)))?;
+                f.write_str(" can only hold ")?;
+                join(f, self.digits.iter())?;
+            },
+            Technique::XYWing | Technique::XYZWing => {
+                if let [pivot, first, second] = self.cells[..] {
+                    write!(f, "pivot {} with wings {} and {}", Name(pivot), Name(first), Name(second))?;
+                }
+            },
+            Technique::Contradiction => {
+                if let (Some(&cell), Some(&digit)) = (self.cells.first(), self.digits.first()) {
+                    write!(f, "{} = {} leads to a contradiction", Name(cell), digit)?;
+                }
+            },
+        }
+
+        if !self.eliminations.is_empty() {
+            f.write_str(" => ")?;
+            for (index, &(cell, digit)) in self.eliminations.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{} <> {}", Name(cell), digit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WalkthroughStyle {
+    Steps,
+    Snapshots,
+    Placements,
+}
+
+// Snapshots replay the steps under the same rules that found them.
+pub struct Walkthrough<'a> {
+    logic: Logic,
+    steps: &'a [Step],
+    style: WalkthroughStyle,
+}
+
+impl<'a> Walkthrough<'a> {
+    pub(crate) fn new(logic: Logic, steps: &'a [Step], style: WalkthroughStyle) -> Walkthrough<'a> {
+        Walkthrough {
+            logic,
+            steps,
+            style,
+        }
+    }
+}
+
+impl fmt::Display for Walkthrough<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.style == WalkthroughStyle::Placements {
+            for (index, (cell, digit)) in self.steps.iter().filter_map(|x| x.placement).enumerate() {
+                writeln!(f, "{}. {} = {}", index + 1, Name(cell), digit)?;
+            }
+            return Ok(());
+        }
+
+        let mut logic = self.logic.clone();
+        for (index, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{}. {}", index + 1, step)?;
+            if self.style == WalkthroughStyle::Snapshots {
+                logic.apply(step);
+                if step.placement.is_none() {
+                    writeln!(f, "{}", logic.grid.candidates())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Name<T>(T);
+
+impl fmt::Display for Name<Cell> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}c{}", self.0.row(), self.0.column())
+    }
+}
+
+fn join<T: fmt::Display, I: ExactSizeIterator<Item = T>>(f: &mut fmt::Formatter<'_>, items: I) -> fmt::Result {
+    let len = items.len();
+    for (index, item) in items.enumerate() {
+        if index > 0 {
+            f.write_str(if index + 1 == len { " and " } else { ", " })?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+fn names(f: &mut fmt::Formatter<'_>, groups: &[Group]) -> fmt::Result {
+    let kind = match groups.first() {
+        Some(Group::Row(_)) => "row",
+        Some(Group::Column(_)) => "column",
+        Some(Group::Block(_)) => "block",
+        None => return Ok(()),
+    };
+    write!(f, "{}{} ", kind, if groups.len() > 1 { "s" } else { "" })?;
+    join(f, groups.iter())
+}
+
+const ALL_CELLS: u128 = (1 << 81) - 1;
+
+fn mask<I: IntoIterator<Item = Cell>>(cells: I) -> u128 {
+    cells.into_iter().fold(0, |mask, cell| mask | bit(cell))
+}
+
+fn peers(cell: Cell) -> u128 {
+    mask(cell.neighbors().iter().copied())
+}
+
+fn digits(options: u16) -> impl Iterator<Item = Digit> {
+    DIGITS.iter().copied().filter(move |&x| options & (1 << (x as usize)) != 0)
+}
+
+fn groups() -> impl Iterator<Item = Group> {
+    BLOCKS.iter().chain(ROWS.iter()).chain(COLUMNS.iter()).copied()
+}
+
+fn subsets(len: usize, size: u32) -> impl Iterator<Item = u16> {
+    (0..(1u16 << len)).filter(move |x| x.count_ones() == size)
+}
+
+fn pick<T: Copy>(items: &[T], subset: u16) -> impl Iterator<Item = T> + '_ {
+    items.iter().enumerate().filter(move |&(index, _)| subset & (1 << index) != 0).map(|(_, &x)| x)
+}
+
+// A house the techniques look at, with the classic group it matches if any.
+#[derive(Debug, Clone, Copy)]
+struct House {
+    group: Option<Group>,
+    cells: [Cell; 9],
+    mask: u128,
+}
+
+impl House {
+    fn new(group: Option<Group>, cells: [Cell; 9]) -> House {
+        House {
+            group,
+            cells,
+            mask: mask(cells.iter().copied()),
+        }
+    }
+
+    fn is_line(&self) -> bool {
+        matches!(self.group, Some(Group::Row(_)) | Some(Group::Column(_)))
+    }
+}
+
+// The houses of a puzzle, the cells that cannot share a digit with each cell, and the sums of
+// killer cages along with those derived from them by the rule of 45.
+#[derive(Debug)]
+pub(crate) struct Rules {
+    houses: Vec<House>,
+    peers: [u128; 81],
+    cages: Vec<Sum>,
+    derived: Vec<Sum>,
+}
+
+impl Rules {
+    fn classic() -> Rules {
+        let mut peers = [0; 81];
+        for &cell in &CELLS {
+            peers[cell.index() as usize] = self::peers(cell);
+        }
+        Rules {
+            houses: groups().map(|x| House::new(Some(x), *x.cells())).collect(),
+            peers,
+            cages: Vec::new(),
+            derived: Vec::new(),
+        }
+    }
+
+    // Classic groups keep their usual order so that variants find the same steps on them.
+    pub(crate) fn new(houses: &[[Cell; 9]], peers: [u128; 81]) -> Rules {
+        let houses: Vec<House> = houses.iter().map(|&x| House::new(None, x)).collect();
+        let classic = groups()
+            .filter(|&x| houses.iter().any(|y| y.mask == mask(x)))
+            .map(|x| House::new(Some(x), *x.cells()));
+        let extra = houses.iter().copied().filter(|x| groups().all(|y| mask(y) != x.mask));
+        Rules {
+            houses: classic.chain(extra).collect(),
+            peers,
+            cages: Vec::new(),
+            derived: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_sums(mut self, cages: &[Sum], derived: &[Sum]) -> Rules {
+        self.cages = cages.to_vec();
+        self.derived = derived.to_vec();
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Logic {
+    pub(crate) grid: Grid,
+    placed: u128,
+    rules: Rc<Rules>,
+}
+
+impl Logic {
+    pub(crate) fn new(puzzle: &Grid) -> Logic {
+        Logic::with_rules(puzzle, Rc::new(Rules::classic()))
+    }
+
+    pub(crate) fn with_rules(puzzle: &Grid, rules: Rc<Rules>) -> Logic {
+        let mut logic = Logic {
+            grid: puzzle.clone(),
+            placed: 0,
+            rules,
+        };
+        for &cell in &CELLS {
+            if let ValueState::Defined(digit) = puzzle[cell].state() {
+                logic.place(cell, digit);
+            }
+        }
+        logic
+    }
+
+    pub(crate) fn with_marks(board: &Grid, marks: &Grid) -> Logic {
+        Logic::new(board).marked(marks)
+    }
+
+    pub(crate) fn marked(mut self, marks: &Grid) -> Logic {
+        for cell in cells(ALL_CELLS & !self.placed) {
+            self.grid[cell].options &= marks[cell].options;
+        }
+        self
+    }
+
+    pub(crate) fn is_solved(&self) -> bool {
+        self.placed == ALL_CELLS
+    }
+
+    pub(crate) fn apply(&mut self, step: &Step) {
+        if let Some((cell, digit)) = step.placement {
+            self.place(cell, digit);
+        }
+        for &(cell, digit) in &step.eliminations {
+            self.grid[cell].remove_option(digit);
+        }
+    }
+
+    pub(crate) fn next_step(&self, techniques: &[Technique]) -> Option<Step> {
+        TECHNIQUES.iter().copied()
+            .chain(iter::once(Technique::Contradiction))
+            .filter(|x| techniques.contains(x))
+            .find_map(|x| self.find(x))
+    }
+
+    pub(crate) fn solve(&mut self, techniques: &[Technique]) -> Vec<Step> {
+        let mut steps = Vec::new();
+        while !self.is_solved() {
+            match self.next_step(techniques) {
+                Some(step) => {
+                    self.apply(&step);
+                    steps.push(step);
+                },
+                None => break,
+            }
+        }
+        steps
+    }
+
+    pub(crate) fn find(&self, technique: Technique) -> Option<Step> {
+        match technique {
+            Technique::HiddenSingle => self.hidden_single(),
+            Technique::NakedSingle => self.naked_single(),
+            Technique::CageCombination => self.sum(technique, &self.rules.cages),
+            Technique::RuleOf45 => self.sum(technique, &self.rules.derived),
+            Technique::Pointing => self.pointing(),
+            Technique::Claiming => self.claiming(),
+            Technique::NakedPair => self.naked_subset(technique, 2),
+            Technique::NakedTriple => self.naked_subset(technique, 3),
+            Technique::NakedQuad => self.naked_subset(technique, 4),
+            Technique::HiddenPair => self.hidden_subset(technique, 2),
+            Technique::HiddenTriple => self.hidden_subset(technique, 3),
+            Technique::HiddenQuad => self.hidden_subset(technique, 4),
+            Technique::XWing => self.fish(technique, 2),
+            Technique::Swordfish => self.fish(technique, 3),
+            Technique::Jellyfish => self.fish(technique, 4),
+            Technique::XYWing => self.xy_wing(),
+            Technique::XYZWing => self.xyz_wing(),
+            Technique::Contradiction => self.contradiction(),
+        }
+    }
+
+    fn placements(&self) -> usize {
+        let naked = mask(cells(ALL_CELLS & !self.placed).filter(|&x| self.options(x).count_ones() == 1));
+        let hidden = self.rules.houses.iter()
+            .flat_map(|house| DIGITS.iter().map(move |&digit| self.candidates(&house.cells, digit)))
+            .filter(|x| x.count_ones() == 1)
+            .fold(0, |hidden, x| hidden | x);
+        (naked | hidden).count_ones() as usize
+    }
+
+    fn place(&mut self, cell: Cell, digit: Digit) {
+        self.grid[cell].set(digit);
+        self.placed |= bit(cell);
+        for neighbor in cells(self.peers(cell)) {
+            self.grid[neighbor].remove_option(digit);
+        }
+    }
+
+    fn peers(&self, cell: Cell) -> u128 {
+        self.rules.peers[cell.index() as usize]
+    }
+
+    fn unplaced<'a>(&'a self, cells: &'a [Cell; 9]) -> impl Iterator<Item = Cell> + 'a {
+        cells.iter().copied().filter(move |&x| self.placed & bit(x) == 0)
+    }
+
+    fn options(&self, cell: Cell) -> u16 {
+        self.grid[cell].options
+    }
+
+    fn candidates(&self, cells: &[Cell; 9], digit: Digit) -> u128 {
+        mask(self.unplaced(cells).filter(|&x| self.grid[x].has_option(digit)))
+    }
+
+    fn eliminate(&self, targets: u128, digit: Digit) -> impl Iterator<Item = (Cell, Digit)> + '_ {
+        cells(targets & !self.placed)
+            .filter(move |&x| self.grid[x].has_option(digit))
+            .map(move |x| (x, digit))
+    }
+
+    fn naked_single(&self) -> Option<Step> {
+        CELLS.iter().copied()
+            .filter(|&x| self.placed & bit(x) == 0)
+            .find_map(|cell| match self.grid[cell].state() {
+                ValueState::Defined(digit) => Some(Step::placement(Technique::NakedSingle, Vec::new(), cell, digit)),
+                _ => None,
+            })
+    }
+
+    fn hidden_single(&self) -> Option<Step> {
+        self.rules.houses.iter().find_map(|house| DIGITS.iter().find_map(|&digit| {
+            let candidates = self.candidates(&house.cells, digit);
+            if candidates.count_ones() != 1 {
+                return None;
+            }
+            let cell = cells(candidates).next()?;
+            Some(Step::placement(Technique::HiddenSingle, house.group.into_iter().collect(), cell, digit))
+        }))
+    }
+
+    // Pointing confines a digit from a box or other region to a second house, and claiming from
+    // a line to a region.
+    fn pointing(&self) -> Option<Step> {
+        self.locked(Technique::Pointing, |x| !x.is_line(), |_| true)
+    }
+
+    fn claiming(&self) -> Option<Step> {
+        self.locked(Technique::Claiming, House::is_line, |x| !x.is_line())
+    }
+
+    fn locked<F, G>(&self, technique: Technique, source: F, target: G) -> Option<Step> where
+        F: Fn(&House) -> bool,
+        G: Fn(&House) -> bool,
+    {
+        let houses = &self.rules.houses;
+        houses.iter().filter(|x| source(x)).find_map(|house| DIGITS.iter().find_map(|&digit| {
+            let candidates = self.candidates(&house.cells, digit);
+            if candidates == 0 {
+                return None;
+            }
+            houses.iter().filter(|x| target(x)).find_map(|other| {
+                if other.mask == house.mask || candidates & !other.mask != 0 {
+                    return None;
+                }
+                // Houses outside the classic groups have no name, so the step only lists cells.
+                let groups = match (house.group, other.group) {
+                    (Some(group), Some(other)) => vec![group, other],
+                    _ => Vec::new(),
+                };
+                Step::elimination(
+                    technique,
+                    groups,
+                    cells(candidates).collect(),
+                    vec![digit],
+                    self.eliminate(other.mask & !candidates, digit).collect(),
+                )
+            })
+        }))
+    }
+
+    // A sum only keeps the digits of its completions, and a digit every completion uses leaves the
+    // cells that see all of its places in the sum.
+    fn sum(&self, technique: Technique, sums: &[Sum]) -> Option<Step> {
+        sums.iter().find_map(|sum| {
+            let mut grid = self.grid.clone();
+            sum.restrict(&mut grid)?;
+            let inside = mask(sum.cells.iter().copied());
+            let options = sum.cells.iter().fold(0, |options, &x| options | grid[x].options);
+
+            let mut eliminations: Vec<(Cell, Digit)> = cells(inside & !self.placed)
+                .flat_map(|cell| digits(self.options(cell) & !grid[cell].options).map(move |digit| (cell, digit)))
+                .collect();
+            for digit in digits(sum.required(&grid)) {
+                let places = mask(sum.cells.iter().copied().filter(|&x| grid[x].has_option(digit)));
+                let targets = cells(places).fold(ALL_CELLS, |targets, x| targets & self.peers(x));
+                eliminations.extend(self.eliminate(targets & !inside, digit));
+            }
+            Step::elimination(technique, Vec::new(), sum.cells.clone(), digits(options).collect(), eliminations)
+        })
+    }
+
+    fn naked_subset(&self, technique: Technique, size: u32) -> Option<Step> {
+        self.rules.houses.iter().find_map(|house| {
+            let members: Vec<Cell> = self.unplaced(&house.cells)
+                .filter(|&x| (2..=size).contains(&self.options(x).count_ones()))
+                .collect();
+            subsets(members.len(), size).find_map(|subset| {
+                let options = pick(&members, subset).fold(0, |options, x| options | self.options(x));
+                if options.count_ones() != size {
+                    return None;
+                }
+                let subset_mask = mask(pick(&members, subset));
+                let others = house.mask & !subset_mask;
+                Step::elimination(
+                    technique,
+                    house.group.into_iter().collect(),
+                    pick(&members, subset).collect(),
+                    digits(options).collect(),
+                    digits(options).flat_map(|digit| self.eliminate(others, digit)).collect(),
+                )
+            })
+        })
+    }
+
+    fn hidden_subset(&self, technique: Technique, size: u32) -> Option<Step> {
+        self.rules.houses.iter().find_map(|house| {
+            let members: Vec<(Digit, u128)> = DIGITS.iter()
+                .map(|&digit| (digit, self.candidates(&house.cells, digit)))
+                .filter(|&(_, candidates)| (2..=size).contains(&candidates.count_ones()))
+                .collect();
+            subsets(members.len(), size).find_map(|subset| {
+                let positions = pick(&members, subset).fold(0, |positions, (_, x)| positions | x);
+                if positions.count_ones() != size {
+                    return None;
+                }
+                let options = pick(&members, subset).fold(0u16, |options, (digit, _)| options | 1 << (digit as usize));
+                Step::elimination(
+                    technique,
+                    house.group.into_iter().collect(),
+                    cells(positions).collect(),
+                    digits(options).collect(),
+                    cells(positions)
+                        .flat_map(|cell| digits(self.options(cell) & !options).map(move |digit| (cell, digit)))
+                        .collect(),
+                )
+            })
+        })
+    }
+
+    fn fish(&self, technique: Technique, size: u32) -> Option<Step> {
+        [(&ROWS, &COLUMNS), (&COLUMNS, &ROWS)].iter().find_map(|&(bases, covers)| DIGITS.iter().find_map(|&digit| {
+            let members: Vec<(Group, u128)> = bases.iter()
+                .map(|&base| (base, self.candidates(base.cells(), digit)))
+                .filter(|&(_, candidates)| (2..=size).contains(&candidates.count_ones()))
+                .collect();
+            subsets(members.len(), size).find_map(|subset| {
+                let positions = pick(&members, subset).fold(0, |positions, (_, x)| positions | x);
+                let covers: Vec<Group> = covers.iter().copied().filter(|&x| mask(x) & positions != 0).collect();
+                if covers.len() != size as usize {
+                    return None;
+                }
+                let targets = covers.iter().fold(0, |targets, &x| targets | mask(x)) & !positions;
+                Step::elimination(
+                    technique,
+                    pick(&members, subset).map(|(base, _)| base).chain(covers.iter().copied()).collect(),
+                    cells(positions).collect(),
+                    vec![digit],
+                    self.eliminate(targets, digit).collect(),
+                )
+            })
+        }))
+    }
+
+    fn bivalues(&self, cells: u128) -> impl Iterator<Item = Cell> + '_ {
+        self::cells(cells & !self.placed).filter(move |&x| self.options(x).count_ones() == 2)
+    }
+
+    fn xy_wing(&self) -> Option<Step> {
+        self.bivalues(ALL_CELLS).find_map(|pivot| {
+            let options = self.options(pivot);
+            self.bivalues(self.peers(pivot)).find_map(|first| {
+                let shared = self.options(first) & options;
+                let extra = self.options(first) & !options;
+                if shared.count_ones() != 1 || extra.count_ones() != 1 {
+                    return None;
+                }
+                let wanted = (options & !shared) | extra;
+                self.bivalues(self.peers(pivot)).find_map(|second| {
+                    if self.options(second) != wanted {
+                        return None;
+                    }
+                    let digit = digits(extra).next()?;
+                    Step::elimination(
+                        Technique::XYWing,
+                        Vec::new(),
+                        vec![pivot, first, second],
+                        digits(options | extra).collect(),
+                        self.eliminate(self.peers(first) & self.peers(second), digit).collect(),
+                    )
+                })
+            })
+        })
+    }
+
+    fn xyz_wing(&self) -> Option<Step> {
+        cells(ALL_CELLS & !self.placed)
+            .filter(|&x| self.options(x).count_ones() == 3)
+            .find_map(|pivot| {
+                let options = self.options(pivot);
+                self.bivalues(self.peers(pivot)).find_map(|first| {
+                    if self.options(first) & !options != 0 {
+                        return None;
+                    }
+                    self.bivalues(self.peers(pivot)).find_map(|second| {
+                        let shared = self.options(first) & self.options(second);
+                        if self.options(second) & !options != 0 || shared.count_ones() != 1 {
+                            return None;
+                        }
+                        let digit = digits(shared).next()?;
+                        Step::elimination(
+                            Technique::XYZWing,
+                            Vec::new(),
+                            vec![pivot, first, second],
+                            digits(options).collect(),
+                            self.eliminate(self.peers(pivot) & self.peers(first) & self.peers(second), digit).collect(),
+                        )
+                    })
+                })
+            })
+    }
+
+    fn contradiction(&self) -> Option<Step> {
+        cells(ALL_CELLS & !self.placed).find_map(|cell| self.grid[cell].iter().find_map(|digit| {
+            let mut trial = self.clone();
+            trial.place(cell, digit);
+            if !trial.fails() {
+                return None;
+            }
+            Step::elimination(Technique::Contradiction, Vec::new(), vec![cell], vec![digit], vec![(cell, digit)])
+        }))
+    }
+
+    // Applies singles until they run out, and tells whether a cell or a house ran out of digits.
+    fn fails(&mut self) -> bool {
+        loop {
+            let empty = CELLS.iter().any(|&x| self.options(x) == 0);
+            let missing = self.rules.houses.iter().any(|house| {
+                house.cells.iter().fold(0, |options, &x| options | self.options(x)) != (1 << 9) - 1
+            });
+            if empty || missing {
+                return true;
+            }
+            match self.naked_single().or_else(|| self.hidden_single()) {
+                Some(step) => self.apply(&step),
+                None => return false,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rating {
+    pub score: f32,
+    pub steps: Vec<Step>,
+}
+
+impl Rating {
+    pub(crate) fn new(steps: Vec<Step>) -> Rating {
+        let score = steps.iter().map(Step::rating).fold(1.0, f32::max);
+        Rating { score, steps }
+    }
+
+    pub fn hardest(&self) -> Option<&Step> {
+        self.steps.iter().fold(None, |hardest: Option<&Step>, x| match hardest {
+            Some(hardest) if hardest.rating() >= x.rating() => Some(hardest),
+            _ => Some(x),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogicalSolution {
+    pub solved: bool,
+    pub state: Grid,
+    pub steps: Vec<Step>,
+}
+
+pub fn solve_logically(grid: &Grid, techniques: &[Technique]) -> LogicalSolution {
+    solve_with(Logic::new(grid), techniques)
+}
+
+pub(crate) fn solve_with(mut logic: Logic, techniques: &[Technique]) -> LogicalSolution {
+    let steps = logic.solve(techniques);
+    LogicalSolution {
+        solved: logic.is_solved(),
+        state: logic.grid,
+        steps,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exclusion {
+    Given(Cell, Digit),
+    Entry(Cell, Digit),
+    Deduction(Step),
+}
+
+impl fmt::Display for Exclusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exclusion::Given(cell, digit) => write!(f, "{} is {}", Name(*cell), digit),
+            Exclusion::Entry(cell, digit) => write!(f, "{} holds your {}", Name(*cell), digit),
+            Exclusion::Deduction(step) => write!(f, "{}", step),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hint {
+    Conflict(Cell, Cell),
+    Deduction(Step),
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hint::Conflict(cell, other) => write!(f, "{} and {} hold the same digit", Name(*cell), Name(*other)),
+            Hint::Deduction(step) => write!(f, "{}", step),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotExcluded {
+    Correct,
+    Stalled,
+}
+
+impl fmt::Display for NotExcluded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NotExcluded::Correct => "The digit belongs in this cell",
+            NotExcluded::Stalled => "No known technique rules the digit out",
+        })
+    }
+}
+
+// The givens come from the puzzle. Entries on the board count as well once they agree with the
+// solution, so that the explanation starts from what the player already found.
+pub fn explain_exclusion(puzzle: &Grid, board: &Grid, cell: Cell, digit: Digit) -> Result<Exclusion, NotExcluded> {
+    if let Some(given) = puzzle[cell].state().digit() {
+        return if given == digit { Err(NotExcluded::Correct) } else { Ok(Exclusion::Given(cell, given)) };
+    }
+    if let Some(&peer) = cell.neighbors().iter().find(|&&x| puzzle[x].is(digit)) {
+        return Ok(Exclusion::Given(peer, digit));
+    }
+
+    let mut known = Grid::default();
+    for &x in &CELLS {
+        if puzzle[x].state().is_defined() {
+            known[x] = puzzle[x].clone();
+        }
+    }
+    let mut solutions = puzzle.solutions(2);
+    match solutions.pop() {
+        Some(solution) if solutions.is_empty() => {
+            if solution[cell].is(digit) {
+                return Err(NotExcluded::Correct);
+            }
+            for &x in &CELLS {
+                if board[x].state().is_defined() && board[x].options == solution[x].options {
+                    known[x] = board[x].clone();
+                }
+            }
+        },
+        _ => (),
+    }
+    if let Some(entry) = known[cell].state().digit() {
+        return Ok(Exclusion::Entry(cell, entry));
+    }
+    if let Some(&peer) = cell.neighbors().iter().find(|&&x| known[x].is(digit)) {
+        return Ok(Exclusion::Entry(peer, digit));
+    }
+
+    let mut logic = Logic::new(&known);
+    while !logic.is_solved() {
+        let step = logic.next_step(&TECHNIQUES).ok_or(NotExcluded::Stalled)?;
+        let excluded = match step.placement {
+            Some((x, y)) if x == cell => y != digit,
+            Some((x, y)) => y == digit && peers(cell) & bit(x) != 0,
+            None => step.eliminations.contains(&(cell, digit)),
+        };
+        if excluded {
+            return Ok(Exclusion::Deduction(step));
+        }
+        if step.placement == Some((cell, digit)) {
+            return Err(NotExcluded::Correct);
+        }
+        logic.apply(&step);
+    }
+    Err(NotExcluded::Correct)
+}
+
+const FEW_PLACEMENTS: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DifficultyProfile {
+    pub clues: usize,
+    pub counts: [usize; TECHNIQUES.len()],
+    pub hard_steps: usize,
+    pub bottleneck: Range<usize>,
+    pub rating: Rating,
+}
+
+impl DifficultyProfile {
+    pub fn count(&self, technique: Technique) -> usize {
+        TECHNIQUES.iter().position(|&x| x == technique).map_or(0, |x| self.counts[x])
+    }
+
+    pub fn histogram(&self) -> impl Iterator<Item = (Technique, usize)> + '_ {
+        TECHNIQUES.iter().copied().zip(self.counts.iter().copied()).filter(|&(_, count)| count > 0)
+    }
+}
+
+impl Grid {
+    pub fn difficulty(&self) -> Option<Difficulty> {
+        let solution = solve_logically(self, &TECHNIQUES);
+        if !solution.solved {
+            return None;
+        }
+        Some(solution.steps.iter().map(|x| x.technique.difficulty()).chain(iter::once(Difficulty::Easy)).max().unwrap())
+    }
+
+    pub fn walkthrough<'a>(&self, steps: &'a [Step], style: WalkthroughStyle) -> Walkthrough<'a> {
+        Walkthrough::new(Logic::new(self), steps, style)
+    }
+
+    pub fn hint(&self, marks: Option<&Grid>) -> Option<Hint> {
+        // Deductions from a board that breaks the rules would be meaningless, so point at the clash.
+        if let Some((cell, other)) = self.conflict() {
+            return Some(Hint::Conflict(cell, other));
+        }
+        let logic = match marks {
+            Some(marks) => Logic::with_marks(self, marks),
+            None => Logic::new(self),
+        };
+        logic.next_step(&TECHNIQUES).map(Hint::Deduction)
+    }
+
+    pub fn rate(&self) -> Option<Rating> {
+        self.rate_with(&TECHNIQUES)
+    }
+
+    pub fn rate_with_trial(&self) -> Option<Rating> {
+        let mut techniques = TECHNIQUES.to_vec();
+        techniques.push(Technique::Contradiction);
+        self.rate_with(&techniques)
+    }
+
+    fn rate_with(&self, techniques: &[Technique]) -> Option<Rating> {
+        let solution = solve_logically(self, techniques);
+        if !solution.solved {
+            return None;
+        }
+        Some(Rating::new(solution.steps))
+    }
+
+    pub fn profile(&self) -> Option<DifficultyProfile> {
+        let mut logic = Logic::new(self);
+        let mut steps = Vec::new();
+        let mut placements = Vec::new();
+        while !logic.is_solved() {
+            let step = logic.next_step(&TECHNIQUES)?;
+            placements.push(logic.placements());
+            logic.apply(&step);
+            steps.push(step);
+        }
+
+        let mut counts = [0; TECHNIQUES.len()];
+        for step in &steps {
+            if let Some(index) = TECHNIQUES.iter().position(|&x| x == step.technique) {
+                counts[index] += 1;
+            }
+        }
+
+        let mut bottleneck = 0..0;
+        let mut start = 0;
+        for (index, &count) in placements.iter().enumerate() {
+            if count > FEW_PLACEMENTS {
+                start = index + 1;
+            } else if index + 1 - start > bottleneck.len() {
+                bottleneck = start..index + 1;
+            }
+        }
+
+        Some(DifficultyProfile {
+            clues: CELLS.iter().filter(|&&x| self[x].state().is_defined()).count(),
+            counts,
+            hard_steps: steps.iter().filter(|x| x.technique.difficulty() >= Difficulty::Hard).count(),
+            bottleneck,
+            rating: Rating::new(steps),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        explain_exclusion, solve_logically, Board9, Cell, Digit, Exclusion, Grid, Group, Hint, NotExcluded, Technique,
+        WalkthroughStyle, DIGITS, TECHNIQUES,
+    };
+
+    const PUZZLE: &str = "42....6...6.1....7..5..4....1...7.8...2..1.96..892.......3.2..1.8.....3..4.7.....";
+
+    fn grid(s: &str) -> Grid {
+        Grid::from(&s.parse::<Board9>().unwrap())
+    }
+
+    #[test]
+    fn hint_reports_conflicts_before_deductions() {
+        assert!(matches!(grid(PUZZLE).hint(None), Some(Hint::Deduction(_))));
+
+        let mut board = PUZZLE.to_string();
+        board.replace_range(2..3, "4");
+        assert_eq!(grid(&board).hint(None), Some(Hint::Conflict(Cell::in_row(0, 0), Cell::in_row(0, 2))));
+    }
+
+    #[test]
+    fn explain_exclusion_traces_givens_entries_and_deductions() {
+        let puzzle = grid(PUZZLE);
+        let cell = Cell::in_row(0, 2);
+        let solution = puzzle.solutions(1).pop().unwrap();
+        let digit = solution[cell].state().digit().unwrap();
+        let wrong = DIGITS.iter().copied()
+            .find(|&x| x != digit && cell.neighbors().iter().all(|&y| !puzzle[y].is(x)))
+            .unwrap();
+
+        assert_eq!(explain_exclusion(&puzzle, &puzzle, Cell::in_row(0, 0), Digit::Four), Err(NotExcluded::Correct));
+        assert_eq!(
+            explain_exclusion(&puzzle, &puzzle, Cell::in_row(0, 0), Digit::One),
+            Ok(Exclusion::Given(Cell::in_row(0, 0), Digit::Four)),
+        );
+        assert_eq!(
+            explain_exclusion(&puzzle, &puzzle, cell, Digit::Four),
+            Ok(Exclusion::Given(Cell::in_row(0, 0), Digit::Four)),
+        );
+        assert_eq!(explain_exclusion(&puzzle, &puzzle, cell, digit), Err(NotExcluded::Correct));
+        let deduction = explain_exclusion(&puzzle, &puzzle, cell, wrong);
+        assert!(matches!(deduction, Ok(Exclusion::Deduction(_))));
+
+        // Cells filled by the solver are not givens, and wrong entries are no reason at all.
+        let mut solved = puzzle.clone();
+        solved.solve();
+        assert_eq!(explain_exclusion(&puzzle, &solved, cell, wrong), Ok(Exclusion::Entry(cell, digit)));
+        let mut board = puzzle.clone();
+        board[cell].set(wrong);
+        assert_eq!(explain_exclusion(&puzzle, &board, cell, wrong), deduction);
+
+        let empty = Grid::default();
+        assert_eq!(explain_exclusion(&empty, &empty, cell, Digit::One), Err(NotExcluded::Stalled));
+    }
+
+    #[test]
+    fn profile_counts_every_technique() {
+        let profile = grid(PUZZLE).profile().unwrap();
+        assert_eq!(profile.clues, 25);
+        assert_eq!(profile.count(Technique::HiddenSingle), 51);
+        assert_eq!(profile.count(Technique::XWing), 0);
+        assert_eq!(profile.count(Technique::Contradiction), 0);
+        assert_eq!(profile.histogram().collect::<Vec<_>>(), vec![
+            (Technique::HiddenSingle, 51),
+            (Technique::NakedSingle, 5),
+            (Technique::Pointing, 2),
+            (Technique::Claiming, 1),
+        ]);
+        assert_eq!(profile.counts.iter().sum::<usize>(), profile.rating.steps.len());
+        assert_eq!(profile.hard_steps, 0);
+    }
+
+    #[test]
+    fn rating_follows_sudoku_explainer() {
+        let rating = grid(PUZZLE).rate().unwrap();
+        let singles: Vec<_> = rating.steps.iter().filter(|x| x.technique == Technique::HiddenSingle).collect();
+        assert!(singles.iter().any(|x| matches!(x.groups[0], Group::Block(_))));
+        assert!(singles.iter().any(|x| !matches!(x.groups[0], Group::Block(_))));
+        for step in singles {
+            let expected = if matches!(step.groups[0], Group::Block(_)) { 1.2 } else { 1.5 };
+            assert_eq!(step.rating(), expected);
+        }
+
+        // The score is the hardest step, here the claiming.
+        assert_eq!(rating.hardest().unwrap().technique, Technique::Claiming);
+        assert_eq!(rating.score, 2.8);
+        assert_eq!(rating.score, rating.steps.iter().map(|x| x.rating()).fold(0.0, f32::max));
+    }
+
+    #[test]
+    fn walkthrough_numbers_steps_and_placements() {
+        let puzzle = grid(PUZZLE);
+        let steps = solve_logically(&puzzle, &TECHNIQUES).steps;
+
+        let text = puzzle.walkthrough(&steps, WalkthroughStyle::Steps).to_string();
+        assert_eq!(text.lines().count(), steps.len());
+        assert!(text.starts_with("1. Hidden Single: r3c4 = 2 in block 2\n2. Hidden Single: r3c5 = 6 in block 2\n"));
+        assert!(text.contains("\n4. Hidden Single: r4c3 = 4 in block 4\n"));
+        assert!(text.contains("\n8. Naked Single: r3c8 = 1\n"));
+        assert!(text.contains("\n21. Pointing: 9 in block 8 is confined to column 5 => r2c5 <> 9\n"));
+
+        // Only the placements are listed, numbered on their own.
+        let text = puzzle.walkthrough(&steps, WalkthroughStyle::Placements).to_string();
+        assert_eq!(text.lines().count(), steps.iter().filter(|x| x.placement.is_some()).count());
+        assert!(text.starts_with("1. r3c4 = 2\n2. r3c5 = 6\n3. r1c5 = 7\n4. r4c3 = 4\n"));
+        assert!(!text.contains("Pointing"));
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use crate::{Cell, Digit, Grid, Transform, CELLS, DIGITS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    Central,
+    Quarter,
+    Horizontal,
+    Vertical,
+    Diagonal,
+    AntiDiagonal,
+}
+
+pub const SYMMETRIES: [Symmetry; 6] = [
+    Symmetry::Central,
+    Symmetry::Quarter,
+    Symmetry::Horizontal,
+    Symmetry::Vertical,
+    Symmetry::Diagonal,
+    Symmetry::AntiDiagonal,
+];
+
+impl Symmetry {
+    pub fn transform(self) -> Transform {
+        let central = Transform::rotation().then(&Transform::rotation());
+        match self {
+            Symmetry::Central => central,
+            Symmetry::Quarter => Transform::rotation(),
+            Symmetry::Horizontal => Transform::reflection(),
+            Symmetry::Vertical => central.then(&Transform::reflection()),
+            Symmetry::Diagonal => Transform::transpose(),
+            Symmetry::AntiDiagonal => Transform::transpose().then(&central),
+        }
+    }
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Symmetry::Central => "Central",
+            Symmetry::Quarter => "Quarter Turn",
+            Symmetry::Horizontal => "Horizontal Mirror",
+            Symmetry::Vertical => "Vertical Mirror",
+            Symmetry::Diagonal => "Diagonal",
+            Symmetry::AntiDiagonal => "Anti-Diagonal",
+        })
+    }
+}
+
+impl Grid {
+    pub fn symmetries(&self) -> Vec<Symmetry> {
+        SYMMETRIES.iter().copied()
+            .filter(|&symmetry| {
+                let transform = symmetry.transform();
+                CELLS.iter().all(|&x| {
+                    self[x].state().is_defined() == self[transform.map_cell(x)].state().is_defined()
+                })
+            })
+            .collect()
+    }
+
+    pub fn symmetric_eliminations(&self) -> Vec<(Cell, Digit)> {
+        // Gurth's theorem: an automorphism of a unique puzzle maps its solution onto itself. A cell
+        // the automorphism fixes can only hold a digit the relabelling fixes, and any other cell's
+        // image holds the relabelled digit of the cell.
+        if !self.is_unique() {
+            return Vec::new();
+        }
+
+        let mut state = self.clone();
+        state.solve();
+        let automorphisms = self.automorphisms();
+        let mut eliminations = Vec::new();
+        loop {
+            let mut changed = false;
+            for transform in &automorphisms {
+                for &cell in &CELLS {
+                    let image = transform.map_cell(cell);
+                    let allowed = if image == cell {
+                        DIGITS.iter()
+                            .filter(|&&x| transform.map_digit(x) == x)
+                            .fold(0u16, |options, &x| options | 1 << (x as usize))
+                    } else {
+                        state[cell].iter().fold(0u16, |options, x| options | 1 << (transform.map_digit(x) as usize))
+                    };
+                    for digit in state[image].iter().filter(|&x| allowed & 1 << (x as usize) == 0).collect::<Vec<_>>() {
+                        state[image].options &= !(1 << (digit as usize));
+                        eliminations.push((image, digit));
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        eliminations.sort_by_key(|&(cell, digit)| (cell.index(), digit as u8));
+        eliminations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Board9, Cell, Digit, Grid};
+
+    #[test]
+    fn symmetric_eliminations_fix_the_centre() {
+        // Centrally symmetric with a relabelling that only fixes 9, and the singles leave r5c5 open.
+        let puzzle = "1.....7.......912..89..3.5..3...78.....8.1.....12...6..4.6..91..789.......2.....8";
+        let puzzle = Grid::from(&puzzle.parse::<Board9>().unwrap());
+        let centre = Cell::in_row(4, 4);
+        let eliminations = puzzle.symmetric_eliminations();
+        for &digit in &[Digit::Three, Digit::Four, Digit::Five, Digit::Six] {
+            assert!(eliminations.contains(&(centre, digit)));
+        }
+        assert!(!eliminations.contains(&(centre, Digit::Nine)));
+    }
+}
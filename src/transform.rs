@@ -0,0 +1,502 @@
+use rand::Rng;
+use crate::{below, shuffle, Cell, Digit, Grid, Group, Step, Value, CELLS, DIGITS};
+
+const IDENTITY: [u8; 9] = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Transform {
+    transpose: bool,
+    rows: [u8; 9],
+    columns: [u8; 9],
+    digits: [u8; 9],
+}
+
+fn compose(first: &[u8; 9], second: &[u8; 9]) -> [u8; 9] {
+    let mut result = [0; 9];
+    for (index, &x) in first.iter().enumerate() {
+        result[index] = second[x as usize];
+    }
+    result
+}
+
+fn invert(permutation: &[u8; 9]) -> [u8; 9] {
+    let mut result = [0; 9];
+    for (index, &x) in permutation.iter().enumerate() {
+        result[x as usize] = index as u8;
+    }
+    result
+}
+
+fn random_lines<R: Rng>(rng: &mut R) -> [u8; 9] {
+    let mut bands = [0, 1, 2];
+    shuffle(rng, &mut bands);
+    let mut result = [0; 9];
+    for (index, &band) in bands.iter().enumerate() {
+        let mut order = [0, 1, 2];
+        shuffle(rng, &mut order);
+        for (offset, &line) in order.iter().enumerate() {
+            result[index * 3 + offset] = band * 3 + line;
+        }
+    }
+    result
+}
+
+fn is_order(order: &[u8; 3]) -> bool {
+    order.iter().fold(0u8, |seen, &x| if x < 3 { seen | 1 << x } else { seen }) == 0b111
+}
+
+fn lines(bands: [u8; 3], band: u8, order: [u8; 3]) -> Option<[u8; 9]> {
+    if band >= 3 || !is_order(&bands) || !is_order(&order) {
+        return None;
+    }
+    let mut result = [0; 9];
+    for (index, &x) in bands.iter().enumerate() {
+        for offset in 0..3 {
+            let line = if index as u8 == band { order[offset as usize] } else { offset };
+            result[index * 3 + offset as usize] = x * 3 + line;
+        }
+    }
+    Some(result)
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        transpose: false,
+        rows: IDENTITY,
+        columns: IDENTITY,
+        digits: IDENTITY,
+    };
+
+    pub const fn transpose() -> Transform {
+        Transform {
+            transpose: true,
+            ..Transform::IDENTITY
+        }
+    }
+
+    pub fn rotation() -> Transform {
+        Transform::transpose().then(&Transform::reflection())
+    }
+
+    pub fn reflection() -> Transform {
+        Transform {
+            columns: [8, 7, 6, 5, 4, 3, 2, 1, 0],
+            ..Transform::IDENTITY
+        }
+    }
+
+    pub fn permute_bands(order: [u8; 3]) -> Option<Transform> {
+        Some(Transform {
+            rows: lines(order, 0, [0, 1, 2])?,
+            ..Transform::IDENTITY
+        })
+    }
+
+    pub fn permute_rows(band: u8, order: [u8; 3]) -> Option<Transform> {
+        Some(Transform {
+            rows: lines([0, 1, 2], band, order)?,
+            ..Transform::IDENTITY
+        })
+    }
+
+    pub fn permute_stacks(order: [u8; 3]) -> Option<Transform> {
+        Some(Transform {
+            columns: lines(order, 0, [0, 1, 2])?,
+            ..Transform::IDENTITY
+        })
+    }
+
+    pub fn permute_columns(stack: u8, order: [u8; 3]) -> Option<Transform> {
+        Some(Transform {
+            columns: lines([0, 1, 2], stack, order)?,
+            ..Transform::IDENTITY
+        })
+    }
+
+    pub fn relabel(digits: [Digit; 9]) -> Option<Transform> {
+        let mut result = [0; 9];
+        let mut seen = 0u16;
+        for (index, &digit) in digits.iter().enumerate() {
+            result[index] = digit as u8;
+            seen |= 1 << (digit as u8);
+        }
+        if seen != (1 << 9) - 1 {
+            return None;
+        }
+        Some(Transform {
+            digits: result,
+            ..Transform::IDENTITY
+        })
+    }
+
+    pub fn random<R: Rng>(rng: &mut R) -> Transform {
+        let mut digits = IDENTITY;
+        shuffle(rng, &mut digits);
+        Transform {
+            transpose: below(rng, 2) == 1,
+            rows: random_lines(rng),
+            columns: random_lines(rng),
+            digits,
+        }
+    }
+
+    pub fn then(&self, other: &Transform) -> Transform {
+        let (rows, columns) = if other.transpose {
+            (compose(&self.columns, &other.rows), compose(&self.rows, &other.columns))
+        } else {
+            (compose(&self.rows, &other.rows), compose(&self.columns, &other.columns))
+        };
+        Transform {
+            transpose: self.transpose != other.transpose,
+            rows,
+            columns,
+            digits: compose(&self.digits, &other.digits),
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        let (rows, columns) = if self.transpose {
+            (invert(&self.columns), invert(&self.rows))
+        } else {
+            (invert(&self.rows), invert(&self.columns))
+        };
+        Transform {
+            transpose: self.transpose,
+            rows,
+            columns,
+            digits: invert(&self.digits),
+        }
+    }
+
+    pub fn map_cell(&self, cell: Cell) -> Cell {
+        let (row, column) = (cell.row().index(), cell.column().index());
+        let (row, column) = if self.transpose { (column, row) } else { (row, column) };
+        Cell::in_row(self.rows[row as usize], self.columns[column as usize])
+    }
+
+    pub fn map_digit(&self, digit: Digit) -> Digit {
+        DIGITS[self.digits[digit as usize] as usize]
+    }
+
+    pub fn map_group(&self, group: Group) -> Group {
+        match (group, self.transpose) {
+            (Group::Row(index), false) => Group::Row(self.rows[index as usize]),
+            (Group::Row(index), true) => Group::Column(self.columns[index as usize]),
+            (Group::Column(index), false) => Group::Column(self.columns[index as usize]),
+            (Group::Column(index), true) => Group::Row(self.rows[index as usize]),
+            (Group::Block(_), _) => self.map_cell(group.cells()[0]).block(),
+        }
+    }
+
+    pub fn map_value(&self, value: &Value) -> Value {
+        Value {
+            cell: self.map_cell(value.cell),
+            options: value.iter().fold(0, |options, x| options | 1 << (self.map_digit(x) as usize)),
+        }
+    }
+
+    pub fn map_grid(&self, grid: &Grid) -> Grid {
+        let mut result = Grid::default();
+        for &cell in &CELLS {
+            result[self.map_cell(cell)] = self.map_value(&grid[cell]);
+        }
+        result
+    }
+
+    pub fn map_step(&self, step: &Step) -> Step {
+        Step {
+            technique: step.technique,
+            groups: step.groups.iter().map(|&x| self.map_group(x)).collect(),
+            cells: step.cells.iter().map(|&x| self.map_cell(x)).collect(),
+            digits: step.digits.iter().map(|&x| self.map_digit(x)).collect(),
+            placement: step.placement.map(|(cell, digit)| (self.map_cell(cell), self.map_digit(digit))),
+            eliminations: step.eliminations.iter().map(|&(cell, digit)| (self.map_cell(cell), self.map_digit(digit))).collect(),
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+#[derive(Clone)]
+struct Candidate {
+    transpose: bool,
+    rows: [u8; 9],
+    columns: [u8; 9],
+    labels: [u8; 10],
+    next: u8,
+}
+
+impl Candidate {
+    // Relabels the next row, giving up as soon as it compares above the best line so far.
+    fn extend(&self, values: &[u8; 81], index: usize, row: u8, best: Option<&[u8; 9]>) -> Option<([u8; 9], Candidate)> {
+        let mut labels = self.labels;
+        let mut next = self.next;
+        let mut line = [0; 9];
+        let mut tied = best.is_some();
+        for (column, &source) in self.columns.iter().enumerate() {
+            let value = values[row as usize * 9 + source as usize] as usize;
+            if value != 0 {
+                if labels[value] == 0 {
+                    next += 1;
+                    labels[value] = next;
+                }
+                line[column] = labels[value];
+            }
+            if let (true, Some(best)) = (tied, best) {
+                if line[column] > best[column] {
+                    return None;
+                }
+                tied = line[column] == best[column];
+            }
+        }
+
+        let mut rows = self.rows;
+        rows[index] = row;
+        Some((line, Candidate {
+            transpose: self.transpose,
+            rows,
+            columns: self.columns,
+            labels,
+            next,
+        }))
+    }
+
+    fn rows(&self, index: usize) -> impl Iterator<Item = u8> + '_ {
+        let used = &self.rows[..index];
+        let band = if index % 3 == 0 { None } else { Some(self.rows[index - index % 3] / 3) };
+        (0..9).filter(move |&row| match band {
+            Some(band) => row / 3 == band && !used.contains(&row),
+            None => used.iter().all(|&x| x / 3 != row / 3),
+        })
+    }
+
+    fn transform(&self) -> Transform {
+        let mut rows = [0; 9];
+        let mut columns = [0; 9];
+        for index in 0..9 {
+            rows[self.rows[index] as usize] = index as u8;
+            columns[self.columns[index] as usize] = index as u8;
+        }
+
+        let mut digits = [0; 9];
+        let mut next = self.next;
+        for (digit, &label) in self.labels[1..].iter().enumerate() {
+            digits[digit] = if label == 0 {
+                next += 1;
+                next - 1
+            } else {
+                label - 1
+            };
+        }
+
+        Transform {
+            transpose: self.transpose,
+            rows,
+            columns,
+            digits,
+        }
+    }
+}
+
+// Builds the first line one column at a time, keeping stacks together, and drops every prefix that
+// already compares above the best line.
+struct FirstRow<'a> {
+    transpose: bool,
+    row: u8,
+    values: &'a [u8],
+    best: &'a mut Option<[u8; 9]>,
+    kept: &'a mut Vec<Candidate>,
+}
+
+impl FirstRow<'_> {
+    fn descend(&mut self, columns: &mut [u8; 9], used: u16, labels: [u8; 10], next: u8, line: [u8; 9]) {
+        let position = used.count_ones() as usize;
+        if position == 9 {
+            let mut rows = [0; 9];
+            rows[0] = self.row;
+            let candidate = Candidate {
+                transpose: self.transpose,
+                rows,
+                columns: *columns,
+                labels,
+                next,
+            };
+            keep(self.best, self.kept, line, candidate);
+            return;
+        }
+
+        // A new stack starts every three positions, then its columns follow in any order.
+        let stack = if position % 3 == 0 { None } else { Some(columns[position - position % 3] / 3) };
+        for column in 0..9u8 {
+            let allowed = match stack {
+                Some(stack) => column / 3 == stack && used & 1 << column == 0,
+                None => used & 0b111 << (column / 3 * 3) == 0,
+            };
+            if !allowed {
+                continue;
+            }
+
+            let (mut labels, mut next, mut line) = (labels, next, line);
+            let value = self.values[column as usize] as usize;
+            if value != 0 {
+                if labels[value] == 0 {
+                    next += 1;
+                    labels[value] = next;
+                }
+                line[position] = labels[value];
+            }
+            if matches!(self.best, Some(best) if line[..=position] > best[..=position]) {
+                continue;
+            }
+            columns[position] = column;
+            self.descend(columns, used | 1 << column, labels, next, line);
+        }
+    }
+}
+
+fn keep(best: &mut Option<[u8; 9]>, kept: &mut Vec<Candidate>, line: [u8; 9], candidate: Candidate) {
+    match best {
+        Some(best) if line > *best => return,
+        Some(best) if line == *best => (),
+        _ => {
+            *best = Some(line);
+            kept.clear();
+        },
+    }
+    kept.push(candidate);
+}
+
+impl Grid {
+    pub fn canonical(&self) -> (Grid, Transform) {
+        let transform = self.minlex()[0].transform();
+        (transform.map_grid(self), transform)
+    }
+
+    pub fn disguise<R: Rng>(&self, rng: &mut R) -> (Grid, Transform) {
+        let transform = Transform::random(rng);
+        (transform.map_grid(self), transform)
+    }
+
+    pub fn automorphisms(&self) -> Vec<Transform> {
+        // Every way of reaching the minlex form differs from the first by an automorphism.
+        let candidates = self.minlex();
+        let inverse = candidates[0].transform().inverse();
+        candidates[1..].iter().map(|x| x.transform().then(&inverse)).collect()
+    }
+
+    fn minlex(&self) -> Vec<Candidate> {
+        let mut views = [[0u8; 81]; 2];
+        for &cell in &CELLS {
+            let value = self[cell].state().digit().map_or(0, u8::from);
+            let (row, column) = (cell.row().index() as usize, cell.column().index() as usize);
+            views[0][row * 9 + column] = value;
+            views[1][column * 9 + row] = value;
+        }
+
+        let mut best = None;
+        let mut candidates = Vec::new();
+        for (transpose, values) in views.iter().enumerate() {
+            for row in 0..9 {
+                let start = row as usize * 9;
+                let mut first = FirstRow {
+                    transpose: transpose == 1,
+                    row,
+                    values: &values[start..start + 9],
+                    best: &mut best,
+                    kept: &mut candidates,
+                };
+                first.descend(&mut [0; 9], 0, [0; 10], 0, [0; 9]);
+            }
+        }
+        // Ties keep the order in which the column orders used to be listed, so the chosen transform
+        // does not depend on the search order.
+        candidates.sort_by_cached_key(|x| {
+            let stacks = [x.columns[0] / 3, x.columns[3] / 3, x.columns[6] / 3];
+            (x.transpose, stacks, x.columns.map(|column| column % 3), x.rows[0])
+        });
+
+        for index in 1..9 {
+            let mut best = None;
+            let mut kept = Vec::new();
+            for candidate in &candidates {
+                let values = &views[candidate.transpose as usize];
+                for row in candidate.rows(index) {
+                    if let Some((line, candidate)) = candidate.extend(values, index, row, best.as_ref()) {
+                        keep(&mut best, &mut kept, line, candidate);
+                    }
+                }
+            }
+            candidates = kept;
+        }
+
+        candidates
+    }
+}
+
+pub fn are_equivalent(a: &Grid, b: &Grid) -> Option<Transform> {
+    let (canonical_a, transform_a) = a.canonical();
+    let (canonical_b, transform_b) = b.canonical();
+    if CELLS.iter().all(|&x| canonical_a[x].state().digit() == canonical_b[x].state().digit()) {
+        Some(transform_a.then(&transform_b.inverse()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{are_equivalent, seed_rng, Board9, Grid, Transform, CELLS};
+
+    const PUZZLE: &str = "42....6...6.1....7..5..4....1...7.8...2..1.96..892.......3.2..1.8.....3..4.7.....";
+
+    fn grid(s: &str) -> Grid {
+        Grid::from(&s.parse::<Board9>().unwrap())
+    }
+
+    fn same(a: &Grid, b: &Grid) -> bool {
+        CELLS.iter().all(|&x| a[x].options == b[x].options)
+    }
+
+    #[test]
+    fn then_applies_transforms_in_order() {
+        let mut rng = seed_rng(0);
+        let puzzle = grid(PUZZLE);
+        for _ in 0..20 {
+            let (first, second) = (Transform::random(&mut rng), Transform::random(&mut rng));
+            assert!(same(&first.then(&second).map_grid(&puzzle), &second.map_grid(&first.map_grid(&puzzle))));
+            assert_eq!(first.then(&first.inverse()), Transform::IDENTITY);
+            assert_eq!(first.inverse().then(&first), Transform::IDENTITY);
+        }
+    }
+
+    #[test]
+    fn canonical_is_invariant_under_disguise() {
+        let mut rng = seed_rng(0);
+        let puzzle = grid(PUZZLE);
+        let (canonical, transform) = puzzle.canonical();
+        assert!(same(&transform.map_grid(&puzzle), &canonical));
+        for _ in 0..5 {
+            let (disguised, _) = puzzle.disguise(&mut rng);
+            assert!(same(&disguised.canonical().0, &canonical));
+        }
+    }
+
+    #[test]
+    fn are_equivalent_maps_one_grid_onto_the_other() {
+        let mut rng = seed_rng(0);
+        let puzzle = grid(PUZZLE);
+        for _ in 0..5 {
+            let (disguised, _) = puzzle.disguise(&mut rng);
+            let transform = are_equivalent(&puzzle, &disguised).unwrap();
+            assert!(same(&transform.map_grid(&puzzle), &disguised));
+        }
+
+        let mut other = puzzle.clone();
+        other[CELLS[2]].options = 1 << 2;
+        assert!(are_equivalent(&puzzle, &other).is_none());
+    }
+}
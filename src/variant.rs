@@ -0,0 +1,488 @@
+use rand::Rng;
+use std::rc::Rc;
+use crate::{
+    bit, cage::Sum, cells, search::{self, Constraints, Search}, strategy::{solve_with, Logic, Rules}, Cage, Cell, Grid, GridState,
+    Hint, Layout, LogicalSolution, Rating, Step, Technique, Walkthrough, WalkthroughStyle, CELLS, COLUMNS, ROWS, TECHNIQUES,
+};
+
+// The methods on `Grid` only know the classic rules, so puzzles of any other variant must be
+// solved, checked and generated through their `Variant`.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    houses: Vec<[Cell; 9]>,
+    peers: [u128; 81],
+    cages: Vec<Cage>,
+    sums: Vec<Sum>,
+}
+
+impl Variant {
+    pub fn classic() -> Variant {
+        Variant::jigsaw(&Layout::classic())
+    }
+
+    pub fn jigsaw(layout: &Layout) -> Variant {
+        let mut variant = Variant {
+            houses: Vec::new(),
+            peers: [0; 81],
+            cages: Vec::new(),
+            sums: Vec::new(),
+        };
+        for &groups in &[&ROWS, &COLUMNS] {
+            for &group in groups {
+                variant.add_house(*group.cells());
+            }
+        }
+        for &region in &layout.regions() {
+            variant.add_house(region);
+        }
+        variant
+    }
+
+    pub fn diagonal() -> Variant {
+        Variant::classic().with_diagonals()
+    }
+
+    pub fn with_diagonals(mut self) -> Variant {
+        let mut main = [CELLS[0]; 9];
+        let mut anti = [CELLS[0]; 9];
+        for index in 0..9 {
+            main[index as usize] = Cell::in_row(index, index);
+            anti[index as usize] = Cell::in_row(index, 8 - index);
+        }
+        self.add_house(main);
+        self.add_house(anti);
+        self.derive_sums();
+        self
+    }
+
+    pub fn windoku() -> Variant {
+        Variant::classic().with_windows()
+    }
+
+    pub fn with_windows(mut self) -> Variant {
+        // The four windows force the rows (and columns) outside them to form five hidden windows.
+        let lines: [[u8; 3]; 3] = [[1, 2, 3], [5, 6, 7], [0, 4, 8]];
+        for rows in &lines {
+            for columns in &lines {
+                let mut window = [CELLS[0]; 9];
+                for (index, cell) in window.iter_mut().enumerate() {
+                    *cell = Cell::in_row(rows[index / 3], columns[index % 3]);
+                }
+                self.add_house(window);
+            }
+        }
+        self.derive_sums();
+        self
+    }
+
+    pub fn with_anti_knight(self) -> Variant {
+        self.with_moves(&[(1, 2), (2, 1), (2, -1), (1, -2)])
+    }
+
+    pub fn with_anti_king(self) -> Variant {
+        self.with_moves(&[(0, 1), (1, 1), (1, 0), (1, -1)])
+    }
+
+    fn with_moves(mut self, moves: &[(i8, i8)]) -> Variant {
+        for &cell in &CELLS {
+            let (row, column) = (cell.row().index() as i8, cell.column().index() as i8);
+            for &(down, right) in moves {
+                let (row, column) = (row + down, column + right);
+                if (0..9).contains(&row) && (0..9).contains(&column) {
+                    let other = Cell::in_row(row as u8, column as u8);
+                    self.peers[cell.index() as usize] |= bit(other);
+                    self.peers[other.index() as usize] |= bit(cell);
+                }
+            }
+        }
+        self.derive_sums();
+        self
+    }
+
+    pub fn with_cages(mut self, cages: Vec<Cage>) -> Result<Variant, &'static str> {
+        let mut covered = self.cages.iter().flat_map(|x| &x.cells).fold(0, |mask, &x| mask | bit(x));
+        for cage in &cages {
+            let mask = cage.cells.iter().fold(0, |mask, &x| mask | bit(x));
+            if covered & mask != 0 {
+                return Err("Cages cannot overlap");
+            }
+            covered |= mask;
+        }
+
+        for cage in &cages {
+            let mask = cage.cells.iter().fold(0, |mask, &x| mask | bit(x));
+            for &cell in &cage.cells {
+                self.peers[cell.index() as usize] |= mask & !bit(cell);
+            }
+        }
+        self.cages.extend(cages);
+        self.derive_sums();
+        Ok(self)
+    }
+
+    pub fn houses(&self) -> &[[Cell; 9]] {
+        &self.houses
+    }
+
+    pub fn cages(&self) -> &[Cage] {
+        &self.cages
+    }
+
+    pub fn neighbors(&self, cell: Cell) -> Vec<Cell> {
+        cells(self.peers[cell.index() as usize]).collect()
+    }
+
+    pub fn conflicts(&self, grid: &Grid) -> Vec<(Cell, Cell)> {
+        let mut conflicts = Vec::new();
+        for &cell in &CELLS {
+            if !grid[cell].state().is_defined() {
+                continue;
+            }
+            for neighbor in cells(self.peers[cell.index() as usize]) {
+                if neighbor.index() > cell.index() && grid[neighbor].options == grid[cell].options {
+                    conflicts.push((cell, neighbor));
+                }
+            }
+        }
+        conflicts
+    }
+
+    pub fn is_valid(&self, grid: &Grid) -> bool {
+        self.conflicts(grid).is_empty()
+    }
+
+    pub fn solve(&self, grid: &mut Grid) -> GridState {
+        loop {
+            let state = search::propagate(self, grid);
+            if let GridState::Impossible = state {
+                return state;
+            }
+
+            let mut changed = false;
+            for sum in &self.sums {
+                match sum.restrict(grid) {
+                    Some(restricted) => changed |= restricted,
+                    None => return GridState::Impossible,
+                }
+            }
+            if !changed {
+                return state;
+            }
+        }
+    }
+
+    pub fn count_solutions(&self, grid: &Grid, limit: usize) -> usize {
+        search::count_solutions(&self.constrain(grid), limit)
+    }
+
+    pub fn solutions(&self, grid: &Grid, limit: usize) -> Vec<Grid> {
+        search::solutions(&self.constrain(grid), limit).into_iter().map(|x| x.grid).collect()
+    }
+
+    pub fn is_unique(&self, grid: &Grid) -> bool {
+        search::is_unique(&self.constrain(grid))
+    }
+
+    pub fn is_minimal(&self, grid: &Grid) -> bool {
+        self.is_unique(grid) && !CELLS.iter().any(|&x| self.is_redundant(grid, x))
+    }
+
+    pub fn redundant_clues(&self, grid: &Grid) -> Vec<Cell> {
+        if !self.is_unique(grid) {
+            return Vec::new();
+        }
+        CELLS.iter().copied().filter(|&x| self.is_redundant(grid, x)).collect()
+    }
+
+    pub fn bruteforce<R: Rng>(&self, grid: &mut Grid, rng: &mut R) -> bool {
+        let mut puzzle = self.constrain(grid);
+        let solved = search::bruteforce(&mut puzzle, rng);
+        *grid = puzzle.grid;
+        solved
+    }
+
+    pub fn solution<R: Rng>(&self, rng: &mut R) -> Option<Grid> {
+        let mut grid = Grid::default();
+        if self.bruteforce(&mut grid, rng) {
+            Some(grid)
+        } else {
+            None
+        }
+    }
+
+    pub fn generate<R: Rng>(&self, rng: &mut R) -> Option<Grid> {
+        let solution = self.solution(rng)?;
+        Some(search::dig(&self.constrain(&solution), rng, |_| true).grid)
+    }
+
+    pub fn solve_logically(&self, grid: &Grid, techniques: &[Technique]) -> LogicalSolution {
+        solve_with(self.logic(grid), techniques)
+    }
+
+    pub fn hint(&self, board: &Grid, marks: Option<&Grid>) -> Option<Hint> {
+        if let Some(&(cell, other)) = self.conflicts(board).first() {
+            return Some(Hint::Conflict(cell, other));
+        }
+        let logic = match marks {
+            Some(marks) => self.logic(board).marked(marks),
+            None => self.logic(board),
+        };
+        logic.next_step(&TECHNIQUES).map(Hint::Deduction)
+    }
+
+    pub fn rate(&self, grid: &Grid) -> Option<Rating> {
+        let solution = self.solve_logically(grid, &TECHNIQUES);
+        if !solution.solved {
+            return None;
+        }
+        Some(Rating::new(solution.steps))
+    }
+
+    pub fn walkthrough<'a>(&self, grid: &Grid, steps: &'a [Step], style: WalkthroughStyle) -> Walkthrough<'a> {
+        Walkthrough::new(self.logic(grid), steps, style)
+    }
+
+    fn logic(&self, grid: &Grid) -> Logic {
+        let (cages, derived) = self.sums.split_at(self.cages.len());
+        Logic::with_rules(grid, Rc::new(Rules::new(&self.houses, self.peers).with_sums(cages, derived)))
+    }
+
+    fn is_redundant(&self, grid: &Grid, cell: Cell) -> bool {
+        if !grid[cell].state().is_defined() {
+            return false;
+        }
+        let mut attempt = grid.clone();
+        attempt[cell].reset();
+        self.is_unique(&attempt)
+    }
+
+    fn constrain(&self, grid: &Grid) -> Constrained<'_> {
+        Constrained {
+            variant: self,
+            grid: grid.clone(),
+        }
+    }
+
+    fn derive_sums(&mut self) {
+        if self.cages.is_empty() {
+            return;
+        }
+
+        // Rule of 45: any union of houses sums to a multiple of 45, so the cells left over by the
+        // cages inside it (innies) or sticking out of the cages covering it (outies) have a known sum.
+        let cages: Vec<(u128, i32)> = self.cages.iter()
+            .map(|x| (x.cells.iter().fold(0, |mask, &x| mask | bit(x)), i32::from(x.sum)))
+            .collect();
+        let mut regions: Vec<(u128, i32)> = self.houses.iter()
+            .map(|x| (x.iter().fold(0, |mask, &x| mask | bit(x)), 45))
+            .collect();
+        for &lines in &[&ROWS, &COLUMNS] {
+            for start in 0..9 {
+                for end in start + 2..=9 {
+                    let mask = lines[start..end].iter().flat_map(|x| x.cells()).fold(0, |mask, &x| mask | bit(x));
+                    regions.push((mask, 45 * (end - start) as i32));
+                }
+            }
+        }
+
+        self.sums = self.cages.iter()
+            .map(|x| Sum {
+                cells: x.cells.clone(),
+                total: x.sum,
+                distinct: true,
+            })
+            .collect();
+        let mut seen: Vec<u128> = cages.iter().map(|&(mask, _)| mask).collect();
+
+        for &(region, total) in &regions {
+            let inside = cages.iter().filter(|&&(mask, _)| mask & !region == 0);
+            let innies = inside.clone().fold(region, |mask, &(cage, _)| mask & !cage);
+            self.derive_sum(&mut seen, innies, total - inside.map(|&(_, sum)| sum).sum::<i32>());
+
+            let touching = cages.iter().filter(|&&(mask, _)| mask & region != 0);
+            let covered = touching.clone().fold(0, |mask, &(cage, _)| mask | cage);
+            if region & !covered == 0 {
+                self.derive_sum(&mut seen, covered & !region, touching.map(|&(_, sum)| sum).sum::<i32>() - total);
+            }
+        }
+    }
+
+    fn derive_sum(&mut self, seen: &mut Vec<u128>, mask: u128, total: i32) {
+        let len = mask.count_ones();
+        if len == 0 || len > 9 || total <= 0 || total > 81 || seen.contains(&mask) {
+            return;
+        }
+        seen.push(mask);
+        self.sums.push(Sum {
+            cells: cells(mask).collect(),
+            total: total as u8,
+            distinct: cells(mask).all(|x| mask & !bit(x) & !self.peers[x.index() as usize] == 0),
+        });
+    }
+
+    fn add_house(&mut self, house: [Cell; 9]) {
+        let mask = house.iter().fold(0, |mask, &x| mask | bit(x));
+        for &cell in &house {
+            self.peers[cell.index() as usize] |= mask & !bit(cell);
+        }
+        self.houses.push(house);
+    }
+}
+
+impl Constraints for Variant {
+    type Member = Cell;
+
+    fn digits(&self) -> u32 {
+        (1 << 9) - 1
+    }
+
+    fn houses(&self) -> usize {
+        self.houses.len()
+    }
+
+    fn house(&self, index: usize) -> &[Cell] {
+        &self.houses[index]
+    }
+
+    fn peers<F: FnMut(usize)>(&self, cell: usize, mut visit: F) {
+        for peer in cells(self.peers[cell]) {
+            visit(peer.index() as usize);
+        }
+    }
+}
+
+// A grid searched under the rules of a variant.
+#[derive(Clone)]
+struct Constrained<'a> {
+    variant: &'a Variant,
+    grid: Grid,
+}
+
+impl Search for Constrained<'_> {
+    fn size(&self) -> usize {
+        81
+    }
+
+    fn propagate(&mut self) -> GridState {
+        self.variant.solve(&mut self.grid)
+    }
+
+    fn candidates(&self, cell: usize) -> u32 {
+        u32::from(self.grid[CELLS[cell]].options)
+    }
+
+    fn assign(&mut self, cell: usize, digit: u32) {
+        self.grid[CELLS[cell]].options = 1 << digit;
+    }
+
+    fn clear(&mut self, cell: usize) {
+        self.grid[CELLS[cell]].reset();
+    }
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Variant::classic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{seed_rng, solve_logically, Cell, Digit, Grid, Hint, Variant, WalkthroughStyle, CELLS, TECHNIQUES};
+
+    #[test]
+    fn diagonal_puzzles_follow_the_diagonal_rules() {
+        let variant = Variant::diagonal();
+        let puzzle = variant.generate(&mut seed_rng(0)).unwrap();
+        assert!(variant.is_minimal(&puzzle));
+        assert!(!puzzle.is_unique());
+
+        // Repeat a diagonal clue elsewhere on the diagonal, where the classic rules allow it.
+        let diagonal: Vec<Cell> = (0..9).map(|x| Cell::in_row(x, x)).collect();
+        let &clue = diagonal.iter().find(|&&x| puzzle[x].state().is_defined()).unwrap();
+        let &empty = diagonal.iter()
+            .filter(|&&x| !puzzle[x].state().is_defined())
+            .find(|&&x| x.neighbors().iter().all(|&y| puzzle[y].options != puzzle[clue].options))
+            .unwrap();
+        let mut board = puzzle.clone();
+        board[empty].set(puzzle[clue].state().digit().unwrap());
+
+        let pair = if clue.index() < empty.index() { (clue, empty) } else { (empty, clue) };
+        assert!(variant.check(&puzzle, &board).conflicts.contains(&pair));
+        assert!(puzzle.check(&board).conflicts.is_empty());
+    }
+
+    #[test]
+    fn walkthrough_snapshots_follow_the_variant() {
+        let variant = Variant::diagonal();
+        let puzzle = variant.generate(&mut seed_rng(0)).unwrap();
+        let steps = variant.solve_logically(&puzzle, &TECHNIQUES).steps;
+        let last = steps.iter().position(|x| x.placement.is_none()).unwrap();
+
+        // The candidates after the first elimination, replayed under the diagonal rules.
+        let mut logic = variant.logic(&puzzle);
+        steps[..=last].iter().for_each(|x| logic.apply(x));
+        let snapshot = logic.grid.candidates().to_string();
+
+        let steps = &steps[..=last];
+        assert!(variant.walkthrough(&puzzle, steps, WalkthroughStyle::Snapshots).to_string().contains(&snapshot));
+        assert!(!puzzle.walkthrough(steps, WalkthroughStyle::Snapshots).to_string().contains(&snapshot));
+    }
+
+    #[test]
+    fn techniques_use_the_windows() {
+        let variant = Variant::windoku();
+        let puzzle = variant.generate(&mut seed_rng(5)).unwrap();
+        let solution = variant.solutions(&puzzle, 1).pop().unwrap();
+
+        let logical = variant.solve_logically(&puzzle, &TECHNIQUES);
+        assert!(logical.solved);
+        assert!(!solve_logically(&puzzle, &TECHNIQUES).solved);
+        for step in &logical.steps {
+            assert!(step.placement.iter().all(|&(cell, digit)| solution[cell].is(digit)));
+            assert!(step.eliminations.iter().all(|&(cell, digit)| !solution[cell].is(digit)));
+        }
+        assert!(variant.rate(&puzzle).is_some());
+
+        // r2c2 and r4c4 only share the top-left window.
+        let mut board = Grid::default();
+        board[Cell::in_row(1, 1)].set(Digit::One);
+        board[Cell::in_row(3, 3)].set(Digit::One);
+        assert_eq!(variant.hint(&board, None), Some(Hint::Conflict(Cell::in_row(1, 1), Cell::in_row(3, 3))));
+        assert!(!matches!(board.hint(None), Some(Hint::Conflict(..))));
+    }
+
+    #[test]
+    fn moves_extend_the_peers() {
+        let knight = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+        let king = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+        // Neither pair shares a row, a column or a box.
+        let cases = [
+            (Variant::classic().with_anti_knight(), Cell::in_row(2, 2), Cell::in_row(3, 4), knight),
+            (Variant::classic().with_anti_king(), Cell::in_row(2, 2), Cell::in_row(3, 3), king),
+        ];
+        for (variant, cell, other, moves) in cases.iter() {
+            let mut board = Grid::default();
+            board[*cell].set(Digit::One);
+            board[*other].set(Digit::One);
+            assert_eq!(variant.conflicts(&board), vec![(*cell, *other)]);
+            assert_eq!(variant.check(&Grid::default(), &board).conflicts, vec![(*cell, *other)]);
+            assert!(Variant::classic().conflicts(&board).is_empty());
+
+            let puzzle = variant.generate(&mut seed_rng(1)).unwrap();
+            assert!(variant.is_unique(&puzzle));
+            assert!(!puzzle.is_unique());
+
+            let solution = variant.solutions(&puzzle, 1).pop().unwrap();
+            for &cell in &CELLS {
+                let (row, column) = (cell.row().index() as i8, cell.column().index() as i8);
+                for &(down, right) in moves {
+                    let (row, column) = (row + down, column + right);
+                    if (0..9).contains(&row) && (0..9).contains(&column) {
+                        assert_ne!(solution[cell].options, solution[Cell::in_row(row as u8, column as u8)].options);
+                    }
+                }
+            }
+        }
+    }
+}